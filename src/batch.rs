@@ -0,0 +1,107 @@
+//! Batches of requests, optionally applied all-or-nothing.
+//!
+//! The host has no transactions, so an atomic batch snapshots every path an
+//! item is about to modify and, if a later item fails, writes those
+//! snapshots back in reverse order.
+
+use crate::logging::log;
+use crate::permissions::Permission;
+use crate::request::FsRequest;
+use crate::response::{ErrorCode, FsError, FsResponse, FsResult};
+use crate::snapshot::{restore, snapshot, Snapshot};
+use crate::{execute, validate, State};
+use serde_json::json;
+
+pub fn run(requests: Vec<FsRequest>, atomic: bool, state: &mut State) -> FsResult {
+    if requests
+        .iter()
+        .any(|request| matches!(request, FsRequest::Batch { .. }))
+    {
+        return Err(FsError::new(
+            ErrorCode::InvalidRequest,
+            "Batches cannot be nested",
+        ));
+    }
+
+    log(&format!(
+        "Running batch of {} requests (atomic: {})",
+        requests.len(),
+        atomic
+    ));
+    if atomic {
+        run_atomic(requests, state)
+    } else {
+        run_each(requests, state)
+    }
+}
+
+fn run_each(requests: Vec<FsRequest>, state: &mut State) -> FsResult {
+    let mut results = Vec::new();
+    for mut request in requests {
+        let result = validate(&mut request, state).and_then(|_| execute(request, state));
+        results.push(FsResponse::from(result));
+    }
+    let failed = results.iter().filter(|result| !result.success).count();
+    Ok(Some(json!({
+        "results": results,
+        "succeeded": results.len() - failed,
+        "failed": failed,
+    })))
+}
+
+fn run_atomic(mut requests: Vec<FsRequest>, state: &mut State) -> FsResult {
+    for (i, request) in requests.iter_mut().enumerate() {
+        validate(request, state).map_err(|e| item_error(i, e))?;
+    }
+
+    let mut journal = Vec::new();
+    let mut results = Vec::new();
+    for (i, request) in requests.into_iter().enumerate() {
+        match capture(&request, &mut journal).and_then(|_| execute(request, state)) {
+            Ok(data) => results.push(FsResponse::from(Ok(data))),
+            Err(e) => {
+                log(&format!("Batch item {} failed, rolling back", i));
+                results.push(FsResponse::from(e.clone()));
+                let rollback_errors = rollback(journal, state);
+                return Err(item_error(i, e).with_data(json!({
+                    "results": results,
+                    "rolled_back": true,
+                    "rollback_errors": rollback_errors,
+                })));
+            }
+        }
+    }
+
+    Ok(Some(json!({
+        "results": results,
+        "rolled_back": false,
+    })))
+}
+
+fn item_error(index: usize, e: FsError) -> FsError {
+    e.context(&format!("Batch item {} failed", index))
+}
+
+/// Records the current state of every path `request` may modify.
+fn capture(request: &FsRequest, journal: &mut Vec<(String, Snapshot)>) -> Result<(), FsError> {
+    for (permission, path) in request.access() {
+        if permission != Permission::Read {
+            journal.push((path.to_string(), snapshot(&path)?));
+        }
+    }
+    Ok(())
+}
+
+/// Restores the journal newest-first, returning any errors encountered.
+fn rollback(journal: Vec<(String, Snapshot)>, state: &mut State) -> Vec<String> {
+    let mut errors = Vec::new();
+    for (path, snapshot) in journal.into_iter().rev() {
+        log(&format!("Rolling back: {}", path));
+        state.invalidate(&path);
+        if let Err(e) = restore(&path, snapshot) {
+            log(&format!("Rollback of {} failed: {}", path, e));
+            errors.push(format!("{}: {}", path, e));
+        }
+    }
+    errors
+}
@@ -0,0 +1,146 @@
+//! Content-addressed blob storage under `blobs/`.
+//!
+//! A blob is stored at `blobs/<first two hex digits>/<sha1>`, so identical
+//! content is only stored once and can be verified when it is read back.
+
+use crate::bindings::ntwk::theater::filesystem;
+use crate::hash::sha1_hex;
+use crate::logging::log;
+use crate::ops::{decode_text, path_exists};
+use crate::path::join;
+use crate::permissions::Permission;
+use crate::request::Encoding;
+use crate::response::{ErrorCode, FsError, FsResult};
+use crate::State;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde_json::json;
+
+pub const BLOB_DIR: &str = "blobs";
+
+/// Stores `content` as a blob. The request is checked against the blob
+/// directory; the blob's own path is only known once the content is
+/// hashed, so it is checked here.
+pub fn store(content: &str, encoding: Encoding, state: &State) -> FsResult {
+    let content = decode_text(content, encoding)?;
+    let hash = sha1_hex(content.as_bytes());
+    let path = blob_path(&hash);
+    log(&format!("Storing blob: {}", hash));
+    state.check(Permission::Write, &path)?;
+
+    let existed = path_exists(&path)?;
+    if !existed {
+        let dir = join(BLOB_DIR, &hash[..2]);
+        for dir in [BLOB_DIR, dir.as_str()] {
+            if !path_exists(dir)? {
+                filesystem::create_dir(dir)
+                    .map_err(|e| FsError::host("Failed to create blob directory", e))?;
+            }
+        }
+        filesystem::write_file(&path, &content)
+            .map_err(|e| FsError::host("Failed to write blob", e))?;
+    }
+    Ok(Some(json!({
+        "hash": hash,
+        "path": path,
+        "size": content.len(),
+        "existed": existed,
+    })))
+}
+
+/// Reads a blob back, failing if its content no longer matches its hash.
+pub fn get(hash: &str, encoding: Encoding) -> FsResult {
+    let hash = parse_hash(hash)?;
+    let path = blob_path(&hash);
+    log(&format!("Getting blob: {}", hash));
+
+    if !path_exists(&path)? {
+        return Err(FsError::new(
+            ErrorCode::NotFound,
+            format!("No blob with hash {}", hash),
+        ));
+    }
+    let content =
+        filesystem::read_file(&path).map_err(|e| FsError::host("Failed to read blob", e))?;
+    let actual = sha1_hex(&content);
+    if actual != hash {
+        return Err(FsError::new(
+            ErrorCode::Conflict,
+            format!("Blob {} is corrupt: its content hashes to {}", hash, actual),
+        )
+        .with_data(json!({ "current_hash": actual })));
+    }
+
+    let size = content.len();
+    let content = match encoding {
+        Encoding::Base64 => BASE64.encode(&content),
+        Encoding::Utf8 => String::from_utf8(content).map_err(|_| {
+            FsError::new(
+                ErrorCode::UnsupportedOperation,
+                format!(
+                    "Blob {} is not valid UTF-8; get it with base64 encoding",
+                    hash
+                ),
+            )
+        })?,
+    };
+    Ok(Some(json!({
+        "hash": hash,
+        "content": content,
+        "size": size,
+    })))
+}
+
+fn blob_path(hash: &str) -> String {
+    format!("{}/{}/{}", BLOB_DIR, &hash[..2], hash)
+}
+
+/// The file `get-content` reads for `hash`. An invalid hash reads nothing,
+/// as `get` rejects it, so it only needs the blob directory.
+pub fn read_path(hash: &str) -> String {
+    match parse_hash(hash) {
+        Ok(hash) => blob_path(&hash),
+        Err(_) => BLOB_DIR.to_string(),
+    }
+}
+
+/// Accepts a 40-digit hex SHA-1 in either case.
+fn parse_hash(hash: &str) -> Result<String, FsError> {
+    if hash.len() != 40 || !hash.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(FsError::new(
+            ErrorCode::InvalidRequest,
+            format!("'{}' is not a SHA-1 hash", hash),
+        ));
+    }
+    Ok(hash.to_ascii_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{blob_path, parse_hash, read_path};
+
+    #[test]
+    fn blobs_are_sharded_by_hash_prefix() {
+        let hash = "a9993e364706816aba3e25717850c26c9cd0d89d";
+        assert_eq!(blob_path(hash), format!("blobs/a9/{}", hash));
+    }
+
+    #[test]
+    fn hashes_are_validated() {
+        assert_eq!(
+            parse_hash("A9993E364706816ABA3E25717850C26C9CD0D89D").unwrap(),
+            "a9993e364706816aba3e25717850c26c9cd0d89d"
+        );
+        assert!(parse_hash("a9993e").is_err());
+        assert!(parse_hash("../../../../../../../../../../etc/passwd!").is_err());
+    }
+
+    #[test]
+    fn reads_need_the_blob_file() {
+        assert_eq!(
+            read_path("A9993E364706816ABA3E25717850C26C9CD0D89D"),
+            "blobs/a9/a9993e364706816aba3e25717850c26c9cd0d89d"
+        );
+        assert_eq!(read_path("a9993e"), "blobs");
+    }
+}
@@ -0,0 +1,157 @@
+//! Copying files and directory trees with the per-file host imports.
+//!
+//! The whole copy is planned first (reading every source file, checking for
+//! conflicts and the byte budget) so that most failures are reported before
+//! anything is written.
+
+use crate::bindings::ntwk::theater::filesystem;
+use crate::logging::log;
+use crate::ops::{check_descendants, is_dir, path_exists};
+use crate::path::join;
+use crate::permissions::Permission;
+use crate::response::{ErrorCode, FsError, FsResult};
+use crate::snapshot::remove;
+use crate::State;
+use serde_json::json;
+
+pub struct CopyOptions {
+    pub overwrite: bool,
+    pub skip_existing: bool,
+    pub max_bytes: Option<u64>,
+}
+
+enum Step {
+    Remove(String),
+    CreateDir(String),
+    WriteFile(String, String),
+}
+
+#[derive(Default)]
+struct Plan {
+    steps: Vec<Step>,
+    skipped: Vec<String>,
+    bytes: u64,
+}
+
+pub fn copy(source: &str, destination: &str, options: &CopyOptions, state: &State) -> FsResult {
+    log(&format!("Copying {} to {}", source, destination));
+    if options.overwrite && options.skip_existing {
+        return Err(FsError::new(
+            ErrorCode::InvalidRequest,
+            "overwrite and skip_existing cannot both be set",
+        ));
+    }
+    if destination == "."
+        || destination == source
+        || destination.starts_with(&format!("{}/", source))
+        || source == "."
+    {
+        return Err(FsError::new(
+            ErrorCode::InvalidRequest,
+            format!("Cannot copy '{}' into itself", source),
+        ));
+    }
+    if !path_exists(source)? {
+        return Err(FsError::new(
+            ErrorCode::NotFound,
+            format!("Source does not exist: {}", source),
+        ));
+    }
+
+    let mut plan = Plan::default();
+    plan_copy(source, destination, options, state, &mut plan)?;
+
+    let mut files_copied = 0;
+    let mut dirs_created = 0;
+    for step in plan.steps {
+        match step {
+            Step::Remove(path) => {
+                remove(&path).map_err(|e| FsError::host("Failed to replace destination", e))?;
+            }
+            Step::CreateDir(path) => {
+                filesystem::create_dir(&path)
+                    .map_err(|e| FsError::host("Failed to create directory", e))?;
+                dirs_created += 1;
+            }
+            Step::WriteFile(path, content) => {
+                filesystem::write_file(&path, &content)
+                    .map_err(|e| FsError::host("Failed to write file", e))?;
+                files_copied += 1;
+            }
+        }
+    }
+
+    Ok(Some(json!({
+        "kind": if is_dir(source) { "directory" } else { "file" },
+        "files_copied": files_copied,
+        "dirs_created": dirs_created,
+        "bytes_copied": plan.bytes,
+        "skipped": plan.skipped,
+    })))
+}
+
+fn plan_copy(
+    source: &str,
+    destination: &str,
+    options: &CopyOptions,
+    state: &State,
+    plan: &mut Plan,
+) -> Result<(), FsError> {
+    state.check(Permission::Read, source)?;
+    state.check(Permission::Write, destination)?;
+    let source_is_dir = is_dir(source);
+    if path_exists(destination)? {
+        let both_dirs = source_is_dir && is_dir(destination);
+        if !both_dirs {
+            if options.skip_existing {
+                plan.skipped.push(destination.to_string());
+                return Ok(());
+            }
+            if !options.overwrite {
+                return Err(FsError::new(
+                    ErrorCode::Conflict,
+                    format!("Destination already exists: {}", destination),
+                ));
+            }
+            state.check(Permission::Delete, destination)?;
+            check_descendants(destination, Permission::Delete, state)?;
+            plan.steps.push(Step::Remove(destination.to_string()));
+            if source_is_dir {
+                plan.steps.push(Step::CreateDir(destination.to_string()));
+            }
+        }
+    } else if source_is_dir {
+        plan.steps.push(Step::CreateDir(destination.to_string()));
+    }
+
+    if source_is_dir {
+        let entries =
+            filesystem::list_files(source).map_err(|e| FsError::host("Failed to list files", e))?;
+        for name in entries {
+            plan_copy(
+                &join(source, &name),
+                &join(destination, &name),
+                options,
+                state,
+                plan,
+            )?;
+        }
+        return Ok(());
+    }
+
+    let content =
+        filesystem::read_file(source).map_err(|e| FsError::host("Failed to read file", e))?;
+    plan.bytes += content.len() as u64;
+    if let Some(max_bytes) = options.max_bytes {
+        if plan.bytes > max_bytes {
+            return Err(FsError::new(
+                ErrorCode::LimitExceeded,
+                format!("Copy exceeds the budget of {} bytes", max_bytes),
+            ));
+        }
+    }
+    let content = String::from_utf8(content).map_err(|_| FsError::not_text(source))?;
+    plan.steps
+        .push(Step::WriteFile(destination.to_string(), content));
+    Ok(())
+}
@@ -0,0 +1,280 @@
+//! Unified diffs between files, or a file and proposed content.
+
+use crate::logging::log;
+use crate::ops::{path_exists, read_text};
+use crate::response::{ErrorCode, FsError, FsResult};
+use serde_json::json;
+use std::fmt::Write;
+
+const DEFAULT_CONTEXT: usize = 3;
+/// The trace kept to recover the edit script grows with the square of the
+/// number of changed lines, so larger diffs are refused.
+const MAX_EDIT_DISTANCE: usize = 2000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Equal(usize, usize),
+    Delete(usize),
+    Insert(usize),
+}
+
+/// Diffs `path` against `other`, or against `content` when no other path
+/// is given. When diffing against content, a missing `path` diffs as empty
+/// so new files can be previewed.
+pub fn diff(
+    path: &str,
+    other: Option<&str>,
+    content: Option<&str>,
+    context: Option<usize>,
+) -> FsResult {
+    let context = context.unwrap_or(DEFAULT_CONTEXT);
+    let (old_name, old, new_name, new) = match (other, content) {
+        (Some(other), None) => {
+            log(&format!("Diffing {} against {}", path, other));
+            (
+                format!("a/{}", path),
+                read_text(path)?,
+                format!("b/{}", other),
+                read_text(other)?,
+            )
+        }
+        (None, Some(content)) => {
+            log(&format!("Diffing {} against supplied content", path));
+            let (old_name, old) = if path_exists(path)? {
+                (format!("a/{}", path), read_text(path)?)
+            } else {
+                ("/dev/null".to_string(), String::new())
+            };
+            (old_name, old, format!("b/{}", path), content.to_string())
+        }
+        _ => {
+            return Err(FsError::new(
+                ErrorCode::InvalidRequest,
+                "Exactly one of other and content must be provided",
+            ))
+        }
+    };
+
+    let (diff, added, removed) = unified_diff(&old, &new, &old_name, &new_name, context)
+        .ok_or_else(|| {
+            FsError::new(
+                ErrorCode::LimitExceeded,
+                format!(
+                    "Files differ by more than {} lines; too large to diff",
+                    MAX_EDIT_DISTANCE
+                ),
+            )
+        })?;
+    Ok(Some(json!({
+        "diff": diff,
+        "changed": !diff.is_empty(),
+        "added": added,
+        "removed": removed,
+    })))
+}
+
+/// Renders the unified diff from `old` to `new`, returning it with the
+/// number of lines added and removed. Identical inputs give an empty diff;
+/// `None` if they differ by more than `MAX_EDIT_DISTANCE` lines.
+fn unified_diff(
+    old: &str,
+    new: &str,
+    old_name: &str,
+    new_name: &str,
+    context: usize,
+) -> Option<(String, usize, usize)> {
+    let a: Vec<&str> = old.split_inclusive('\n').collect();
+    let b: Vec<&str> = new.split_inclusive('\n').collect();
+    let ops = diff_lines(&a, &b, MAX_EDIT_DISTANCE)?;
+    let added = ops.iter().filter(|op| matches!(op, Op::Insert(_))).count();
+    let removed = ops.iter().filter(|op| matches!(op, Op::Delete(_))).count();
+    if added == 0 && removed == 0 {
+        return Some((String::new(), 0, 0));
+    }
+
+    let mut output = format!("--- {}\n+++ {}\n", old_name, new_name);
+    for (start, end) in hunks(&ops, context) {
+        let hunk = &ops[start..end];
+        // Line positions in each file at the start of the hunk
+        let (old_pos, new_pos) = ops[..start].iter().fold((0, 0), |(o, n), op| match op {
+            Op::Equal(..) => (o + 1, n + 1),
+            Op::Delete(_) => (o + 1, n),
+            Op::Insert(_) => (o, n + 1),
+        });
+        let old_len = hunk
+            .iter()
+            .filter(|op| !matches!(op, Op::Insert(_)))
+            .count();
+        let new_len = hunk
+            .iter()
+            .filter(|op| !matches!(op, Op::Delete(_)))
+            .count();
+        let _ = writeln!(
+            output,
+            "@@ -{} +{} @@",
+            range(old_pos, old_len),
+            range(new_pos, new_len)
+        );
+        for op in hunk {
+            let (prefix, line) = match *op {
+                Op::Equal(i, _) => (' ', a[i]),
+                Op::Delete(i) => ('-', a[i]),
+                Op::Insert(j) => ('+', b[j]),
+            };
+            output.push(prefix);
+            output.push_str(line);
+            if !line.ends_with('\n') {
+                output.push_str("\n\\ No newline at end of file\n");
+            }
+        }
+    }
+    Some((output, added, removed))
+}
+
+/// A hunk header range; empty ranges name the line before them.
+fn range(pos: usize, len: usize) -> String {
+    match len {
+        0 => format!("{},0", pos),
+        1 => format!("{}", pos + 1),
+        _ => format!("{},{}", pos + 1, len),
+    }
+}
+
+/// Groups changes into hunks of `ops` indices, merging changes separated
+/// by no more than twice the context.
+fn hunks(ops: &[Op], context: usize) -> Vec<(usize, usize)> {
+    let mut hunks: Vec<(usize, usize)> = Vec::new();
+    for (i, op) in ops.iter().enumerate() {
+        if matches!(op, Op::Equal(..)) {
+            continue;
+        }
+        let start = i.saturating_sub(context);
+        let end = (i + 1).saturating_add(context).min(ops.len());
+        match hunks.last_mut() {
+            Some(last) if start <= last.1 => last.1 = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+    hunks
+}
+
+/// Myers' shortest edit script between two sequences of lines, or `None`
+/// if it needs more than `max_d` insertions and deletions.
+fn diff_lines(a: &[&str], b: &[&str], max_d: usize) -> Option<Vec<Op>> {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let max = n + m;
+    let index = |k: isize| (k + max) as usize;
+    let mut v = vec![0isize; 2 * max as usize + 2];
+    // Step d only reads diagonals -d..=d of the previous step, so that
+    // window is all the backtrack needs
+    let mut trace: Vec<Vec<isize>> = Vec::new();
+
+    'search: for d in 0..=max {
+        if d as usize > max_d {
+            return None;
+        }
+        trace.push(v[index(-d)..=index(d)].to_vec());
+        for k in (-d..=d).step_by(2) {
+            let mut x = if k == -d || (k != d && v[index(k - 1)] < v[index(k + 1)]) {
+                v[index(k + 1)]
+            } else {
+                v[index(k - 1)] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[index(k)] = x;
+            if x >= n && y >= m {
+                break 'search;
+            }
+        }
+    }
+
+    let mut ops = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for (d, v) in trace.iter().enumerate().skip(1).rev() {
+        let d = d as isize;
+        let window = |k: isize| (k + d) as usize;
+        let k = x - y;
+        let prev_k = if k == -d || (k != d && v[window(k - 1)] < v[window(k + 1)]) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let prev_x = v[window(prev_k)];
+        let prev_y = prev_x - prev_k;
+        while x > prev_x && y > prev_y {
+            x -= 1;
+            y -= 1;
+            ops.push(Op::Equal(x as usize, y as usize));
+        }
+        if x == prev_x {
+            ops.push(Op::Insert(prev_y as usize));
+        } else {
+            ops.push(Op::Delete(prev_x as usize));
+        }
+        x = prev_x;
+        y = prev_y;
+    }
+    // The common prefix before the first edit
+    while x > 0 {
+        x -= 1;
+        y -= 1;
+        ops.push(Op::Equal(x as usize, y as usize));
+    }
+    ops.reverse();
+    Some(ops)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{diff_lines, unified_diff};
+
+    #[test]
+    fn identical_inputs_have_no_diff() {
+        assert_eq!(
+            unified_diff("a\nb\n", "a\nb\n", "a/f", "b/f", 3).unwrap().0,
+            ""
+        );
+    }
+
+    #[test]
+    fn changes_are_grouped_with_context() {
+        let old = "1\n2\n3\n4\n5\n6\n7\n8\n9\n";
+        let new = "1\n2\nthree\n4\n5\n6\n7\n8\nnine\n";
+        let (diff, added, removed) = unified_diff(old, new, "a/f", "b/f", 1).unwrap();
+        assert_eq!(
+            diff,
+            "--- a/f\n+++ b/f\n@@ -2,3 +2,3 @@\n 2\n-3\n+three\n 4\n@@ -8,2 +8,2 @@\n 8\n-9\n+nine\n"
+        );
+        assert_eq!((added, removed), (2, 2));
+
+        let (diff, ..) = unified_diff(old, new, "a/f", "b/f", 3).unwrap();
+        assert_eq!(diff.matches("@@ -").count(), 1);
+    }
+
+    #[test]
+    fn new_files_and_missing_newlines() {
+        let (diff, ..) = unified_diff("", "x\ny", "/dev/null", "b/f", 3).unwrap();
+        assert_eq!(
+            diff,
+            "--- /dev/null\n+++ b/f\n@@ -0,0 +1,2 @@\n+x\n+y\n\\ No newline at end of file\n"
+        );
+    }
+
+    #[test]
+    fn large_edit_distances_are_refused() {
+        let a = ["1\n", "2\n", "3\n"];
+        let b = ["x\n", "2\n", "y\n"];
+        assert_eq!(diff_lines(&a, &b, 4).unwrap().len(), 5);
+        assert_eq!(diff_lines(&a, &b, 3), None);
+        assert_eq!(
+            unified_diff("a\n", "b\n", "a/f", "b/f", usize::MAX)
+                .unwrap()
+                .0,
+            "--- a/f\n+++ b/f\n@@ -1 +1 @@\n-a\n+b\n"
+        );
+    }
+}
@@ -0,0 +1,309 @@
+//! Text replacement and line-based edits.
+
+use crate::bindings::ntwk::theater::filesystem;
+use crate::hash::sha1_hex;
+use crate::logging::log;
+use crate::ops::read_text;
+use crate::response::{ErrorCode, FsError, FsResult};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+/// Replace `old_text` with `new_text`. By default `old_text` must occur
+/// exactly once; `replace_all` replaces every occurrence and `occurrence`
+/// picks one (counting from 1).
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Edit {
+    pub old_text: String,
+    pub new_text: String,
+    #[serde(default)]
+    pub replace_all: bool,
+    #[serde(default)]
+    pub occurrence: Option<usize>,
+}
+
+pub fn edit_file(path: &str, edit: &Edit) -> FsResult {
+    log(&format!("Editing file: {}", path));
+    let content = read_text(path)?;
+    let (content_str, replacements) = apply_edit(&content, edit)?;
+    filesystem::write_file(path, &content_str)
+        .map_err(|e| FsError::host("Failed to write edited file", e))?;
+    Ok(Some(json!({
+        "replacements": replacements,
+        "hash": sha1_hex(content_str.as_bytes()),
+    })))
+}
+
+/// Applies `edits` in order to the file's contents in memory and writes the
+/// result only if every edit succeeded.
+pub fn multi_edit(path: &str, edits: &[Edit]) -> FsResult {
+    log(&format!("Applying {} edits to: {}", edits.len(), path));
+    if edits.is_empty() {
+        return Err(FsError::new(
+            ErrorCode::InvalidRequest,
+            "At least one edit must be provided",
+        ));
+    }
+
+    let mut content_str = read_text(path)?;
+    let mut replacements = Vec::new();
+    for (i, edit) in edits.iter().enumerate() {
+        let (edited, count) =
+            apply_edit(&content_str, edit).map_err(|e| e.context(&format!("Edit {} failed", i)))?;
+        content_str = edited;
+        replacements.push(count);
+    }
+
+    filesystem::write_file(path, &content_str)
+        .map_err(|e| FsError::host("Failed to write edited file", e))?;
+    Ok(Some(json!({
+        "edits": edits.len(),
+        "replacements": replacements,
+    })))
+}
+
+/// Where `insert-lines` puts its text relative to the given line.
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LinePosition {
+    #[default]
+    Before,
+    After,
+}
+
+pub fn insert_lines(path: &str, line: usize, position: LinePosition, text: &str) -> FsResult {
+    log(&format!("Inserting lines into: {}", path));
+    let content = read_text(path)?;
+    let edited = insert_at(&content, line, position, text)?;
+    filesystem::write_file(path, &edited)
+        .map_err(|e| FsError::host("Failed to write edited file", e))?;
+    Ok(Some(json!({
+        "inserted": text.lines().count(),
+        "total_lines": edited.lines().count(),
+    })))
+}
+
+pub fn delete_lines(path: &str, start_line: usize, end_line: usize) -> FsResult {
+    log(&format!(
+        "Deleting lines {}-{} of: {}",
+        start_line, end_line, path
+    ));
+    let content = read_text(path)?;
+    let edited = delete_range(&content, start_line, end_line)?;
+    filesystem::write_file(path, &edited)
+        .map_err(|e| FsError::host("Failed to write edited file", e))?;
+    Ok(Some(json!({
+        "deleted": end_line - start_line + 1,
+        "total_lines": edited.lines().count(),
+    })))
+}
+
+/// Inserts `text` as whole lines before or after `line` (1-based). After
+/// line 0 is the start of the file, which is also how to fill an empty file.
+fn insert_at(
+    content: &str,
+    line: usize,
+    position: LinePosition,
+    text: &str,
+) -> Result<String, FsError> {
+    if text.is_empty() {
+        return Err(FsError::new(
+            ErrorCode::InvalidRequest,
+            "text must not be empty",
+        ));
+    }
+    let lines: Vec<&str> = content.split_inclusive('\n').collect();
+    let index = match position {
+        LinePosition::Before if (1..=lines.len()).contains(&line) => line - 1,
+        LinePosition::After if line <= lines.len() => line,
+        _ => {
+            return Err(FsError::new(
+                ErrorCode::InvalidRequest,
+                format!("Line {} does not exist ({} lines)", line, lines.len()),
+            ))
+        }
+    };
+
+    let ending = if content.contains("\r\n") {
+        "\r\n"
+    } else {
+        "\n"
+    };
+    let mut edited = lines[..index].concat();
+    if !edited.is_empty() && !edited.ends_with('\n') {
+        edited.push_str(ending);
+    }
+    edited.push_str(text);
+    if !text.ends_with('\n') {
+        edited.push_str(ending);
+    }
+    edited.push_str(&lines[index..].concat());
+    Ok(edited)
+}
+
+/// Removes lines `start` through `end` (1-based, inclusive).
+fn delete_range(content: &str, start: usize, end: usize) -> Result<String, FsError> {
+    let lines: Vec<&str> = content.split_inclusive('\n').collect();
+    if start == 0 || end < start || end > lines.len() {
+        return Err(FsError::new(
+            ErrorCode::InvalidRequest,
+            format!(
+                "Lines {}-{} do not exist ({} lines)",
+                start,
+                end,
+                lines.len()
+            ),
+        ));
+    }
+    Ok(lines[..start - 1].concat() + &lines[end..].concat())
+}
+
+/// Applies `edit` to `content`, returning the new content and the number
+/// of replacements made.
+pub fn apply_edit(content: &str, edit: &Edit) -> Result<(String, usize), FsError> {
+    if edit.old_text.is_empty() {
+        return Err(FsError::new(
+            ErrorCode::InvalidRequest,
+            "old_text must not be empty",
+        ));
+    }
+    if edit.replace_all && edit.occurrence.is_some() {
+        return Err(FsError::new(
+            ErrorCode::InvalidRequest,
+            "replace_all and occurrence cannot both be set",
+        ));
+    }
+
+    let positions: Vec<usize> = content
+        .match_indices(&edit.old_text)
+        .map(|(i, _)| i)
+        .collect();
+    let count = positions.len();
+    if count == 0 {
+        return Err(FsError::new(ErrorCode::NotFound, "old_text not found"));
+    }
+    if edit.replace_all {
+        return Ok((content.replace(&edit.old_text, &edit.new_text), count));
+    }
+
+    let index = match edit.occurrence {
+        Some(occurrence) if occurrence == 0 || occurrence > count => {
+            return Err(FsError::new(
+                ErrorCode::NotFound,
+                format!(
+                    "Occurrence {} of old_text not found ({} found)",
+                    occurrence, count
+                ),
+            ))
+        }
+        Some(occurrence) => positions[occurrence - 1],
+        None if count > 1 => {
+            return Err(FsError::new(
+                ErrorCode::Ambiguous,
+                format!(
+                    "old_text found {} times; set replace_all or occurrence",
+                    count
+                ),
+            )
+            .with_data(json!({ "count": count })))
+        }
+        None => positions[0],
+    };
+
+    let mut edited = String::with_capacity(content.len() + edit.new_text.len());
+    edited.push_str(&content[..index]);
+    edited.push_str(&edit.new_text);
+    edited.push_str(&content[index + edit.old_text.len()..]);
+    Ok((edited, 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{apply_edit, delete_range, insert_at, Edit, LinePosition};
+    use crate::response::ErrorCode;
+
+    fn edit(old_text: &str, new_text: &str) -> Edit {
+        Edit {
+            old_text: old_text.to_string(),
+            new_text: new_text.to_string(),
+            replace_all: false,
+            occurrence: None,
+        }
+    }
+
+    #[test]
+    fn replaces_a_unique_match() {
+        assert_eq!(
+            apply_edit("let a = 1;", &edit("1", "2")).unwrap(),
+            ("let a = 2;".to_string(), 1)
+        );
+    }
+
+    #[test]
+    fn missing_text_is_not_found() {
+        let error = apply_edit("abc", &edit("x", "y")).unwrap_err();
+        assert_eq!(error.code, ErrorCode::NotFound);
+    }
+
+    #[test]
+    fn several_matches_are_ambiguous() {
+        let error = apply_edit("a a a", &edit("a", "b")).unwrap_err();
+        assert_eq!(error.code, ErrorCode::Ambiguous);
+        assert_eq!(error.data.unwrap()["count"], 3);
+    }
+
+    #[test]
+    fn replace_all_and_occurrence() {
+        let all = Edit {
+            replace_all: true,
+            ..edit("a", "b")
+        };
+        assert_eq!(apply_edit("a a a", &all).unwrap(), ("b b b".to_string(), 3));
+
+        let second = Edit {
+            occurrence: Some(2),
+            ..edit("a", "b")
+        };
+        assert_eq!(
+            apply_edit("a a a", &second).unwrap(),
+            ("a b a".to_string(), 1)
+        );
+
+        let fourth = Edit {
+            occurrence: Some(4),
+            ..edit("a", "b")
+        };
+        assert_eq!(
+            apply_edit("a a a", &fourth).unwrap_err().code,
+            ErrorCode::NotFound
+        );
+    }
+
+    #[test]
+    fn inserts_whole_lines() {
+        let text = "a\nb\nc";
+        assert_eq!(
+            insert_at(text, 2, LinePosition::Before, "x").unwrap(),
+            "a\nx\nb\nc"
+        );
+        assert_eq!(
+            insert_at(text, 3, LinePosition::After, "x\n").unwrap(),
+            "a\nb\nc\nx\n"
+        );
+        assert_eq!(insert_at("", 0, LinePosition::After, "x").unwrap(), "x\n");
+        assert_eq!(
+            insert_at("a\r\nb\r\n", 1, LinePosition::After, "x").unwrap(),
+            "a\r\nx\r\nb\r\n"
+        );
+        assert!(insert_at(text, 4, LinePosition::Before, "x").is_err());
+        assert!(insert_at(text, 0, LinePosition::Before, "x").is_err());
+    }
+
+    #[test]
+    fn deletes_inclusive_ranges() {
+        let text = "a\nb\nc\nd\n";
+        assert_eq!(delete_range(text, 2, 3).unwrap(), "a\nd\n");
+        assert_eq!(delete_range(text, 1, 4).unwrap(), "");
+        assert!(delete_range(text, 3, 5).is_err());
+        assert!(delete_range(text, 3, 2).is_err());
+    }
+}
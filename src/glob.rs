@@ -0,0 +1,305 @@
+//! Minimal path glob matcher.
+//!
+//! Patterns are matched against `/`-separated relative paths:
+//! - `*` matches any run of characters within a single path segment
+//! - `**` matches across segments; `**/` may also match nothing, so
+//!   `src/**/*.rs` matches both `src/lib.rs` and `src/a/b.rs`
+//! - `?` matches exactly one character other than `/`
+//! - `[abc]`, `[a-z]` and `[!a-z]` (or `[^a-z]`) match one character from
+//!   (or not from) the class
+//! - `{a,b}` matches either alternative; alternatives may nest and contain
+//!   any of the above
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Literal(char),
+    Any,
+    Star,
+    GlobStar,
+    GlobStarSlash,
+    Class {
+        negated: bool,
+        ranges: Vec<(char, char)>,
+    },
+}
+
+/// Brace expansion is done up front, so cap how many alternatives a single
+/// pattern can produce.
+const MAX_ALTERNATIVES: usize = 1024;
+
+#[derive(Debug, Clone)]
+pub struct Glob {
+    alternatives: Vec<Vec<Token>>,
+}
+
+/// Returns true if `s` contains any glob metacharacter.
+pub fn is_glob(s: &str) -> bool {
+    s.contains(['*', '?', '[', '{'])
+}
+
+impl Glob {
+    pub fn new(pattern: &str) -> Result<Self, String> {
+        let alternatives = expand_braces(pattern)?
+            .iter()
+            .map(|alternative| tokenize(alternative, pattern))
+            .collect::<Result<_, _>>()?;
+        Ok(Glob { alternatives })
+    }
+
+    /// Number of literal characters in the pattern, used to rank how
+    /// specific one pattern is compared to another. With alternatives, the
+    /// least specific one counts.
+    pub fn literal_len(&self) -> usize {
+        self.alternatives
+            .iter()
+            .map(|tokens| {
+                tokens
+                    .iter()
+                    .filter(|t| matches!(t, Token::Literal(_)))
+                    .count()
+            })
+            .min()
+            .unwrap_or(0)
+    }
+
+    pub fn is_match(&self, path: &str) -> bool {
+        let text: Vec<char> = path.chars().collect();
+        self.alternatives.iter().any(|tokens| {
+            let mut memo = vec![None; (tokens.len() + 1) * (text.len() + 1)];
+            match_at(tokens, 0, 0, &text, &mut memo)
+        })
+    }
+}
+
+fn tokenize(alternative: &str, pattern: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = alternative.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '*' => {
+                if chars.get(i + 1) == Some(&'*') {
+                    if chars.get(i + 2) == Some(&'/') {
+                        tokens.push(Token::GlobStarSlash);
+                        i += 3;
+                    } else {
+                        tokens.push(Token::GlobStar);
+                        i += 2;
+                    }
+                } else {
+                    tokens.push(Token::Star);
+                    i += 1;
+                }
+            }
+            '?' => {
+                tokens.push(Token::Any);
+                i += 1;
+            }
+            '[' => {
+                let (token, next) = parse_class(&chars, i)
+                    .ok_or_else(|| format!("Unterminated character class in '{}'", pattern))?;
+                tokens.push(token);
+                i = next;
+            }
+            c => {
+                tokens.push(Token::Literal(c));
+                i += 1;
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+fn match_at(
+    tokens: &[Token],
+    ti: usize,
+    pi: usize,
+    text: &[char],
+    memo: &mut [Option<bool>],
+) -> bool {
+    let key = ti * (text.len() + 1) + pi;
+    if let Some(result) = memo[key] {
+        return result;
+    }
+    let result = match tokens.get(ti) {
+        None => pi == text.len(),
+        Some(Token::Literal(c)) => {
+            text.get(pi) == Some(c) && match_at(tokens, ti + 1, pi + 1, text, memo)
+        }
+        Some(Token::Any) => {
+            matches!(text.get(pi), Some(c) if *c != '/')
+                && match_at(tokens, ti + 1, pi + 1, text, memo)
+        }
+        Some(Token::Class { negated, ranges }) => match text.get(pi) {
+            Some(c) if *c != '/' => {
+                let in_class = ranges.iter().any(|(lo, hi)| lo <= c && c <= hi);
+                in_class != *negated && match_at(tokens, ti + 1, pi + 1, text, memo)
+            }
+            _ => false,
+        },
+        Some(Token::Star) => {
+            let mut end = pi;
+            loop {
+                if match_at(tokens, ti + 1, end, text, memo) {
+                    break true;
+                }
+                if end == text.len() || text[end] == '/' {
+                    break false;
+                }
+                end += 1;
+            }
+        }
+        Some(Token::GlobStar) => {
+            (pi..=text.len()).any(|end| match_at(tokens, ti + 1, end, text, memo))
+        }
+        Some(Token::GlobStarSlash) => {
+            match_at(tokens, ti + 1, pi, text, memo)
+                || (pi..text.len())
+                    .filter(|&i| text[i] == '/')
+                    .any(|i| match_at(tokens, ti + 1, i + 1, text, memo))
+        }
+    };
+    memo[key] = Some(result);
+    result
+}
+
+/// Expands `{a,b}` alternatives into the full list of brace-free patterns.
+fn expand_braces(pattern: &str) -> Result<Vec<String>, String> {
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut in_class = false;
+    let mut open = None;
+    let mut depth = 0;
+    let mut commas = Vec::new();
+    for (i, &c) in chars.iter().enumerate() {
+        match c {
+            '[' if !in_class => in_class = true,
+            ']' if in_class => in_class = false,
+            '{' if !in_class => {
+                if depth == 0 {
+                    open = Some(i);
+                }
+                depth += 1;
+            }
+            ',' if !in_class && depth == 1 => commas.push(i),
+            '}' if !in_class && depth > 0 => {
+                depth -= 1;
+                if depth == 0 {
+                    let start = open.unwrap_or_default();
+                    let prefix: String = chars[..start].iter().collect();
+                    let suffix: String = chars[i + 1..].iter().collect();
+                    let mut bounds = vec![start];
+                    bounds.extend(&commas);
+                    bounds.push(i);
+
+                    let mut expanded = Vec::new();
+                    for pair in bounds.windows(2) {
+                        let body: String = chars[pair[0] + 1..pair[1]].iter().collect();
+                        // Expanding the rest handles nested and later braces
+                        for alternative in expand_braces(&format!("{}{}{}", prefix, body, suffix))?
+                        {
+                            expanded.push(alternative);
+                            if expanded.len() > MAX_ALTERNATIVES {
+                                return Err(format!(
+                                    "Too many brace alternatives in '{}'",
+                                    pattern
+                                ));
+                            }
+                        }
+                    }
+                    return Ok(expanded);
+                }
+            }
+            _ => {}
+        }
+    }
+    if depth > 0 {
+        return Err(format!("Unterminated brace in '{}'", pattern));
+    }
+    Ok(vec![pattern.to_string()])
+}
+
+fn parse_class(chars: &[char], start: usize) -> Option<(Token, usize)> {
+    let mut i = start + 1;
+    let negated = matches!(chars.get(i), Some('!') | Some('^'));
+    if negated {
+        i += 1;
+    }
+    let mut ranges = Vec::new();
+    let mut first = true;
+    loop {
+        let c = *chars.get(i)?;
+        if c == ']' && !first {
+            return Some((Token::Class { negated, ranges }, i + 1));
+        }
+        first = false;
+        if chars.get(i + 1) == Some(&'-') && chars.get(i + 2).is_some_and(|c| *c != ']') {
+            ranges.push((c, chars[i + 2]));
+            i += 3;
+        } else {
+            ranges.push((c, c));
+            i += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Glob;
+
+    fn matches(pattern: &str, path: &str) -> bool {
+        Glob::new(pattern).unwrap().is_match(path)
+    }
+
+    #[test]
+    fn star_stays_within_a_segment() {
+        assert!(matches("*.rs", "lib.rs"));
+        assert!(!matches("*.rs", "src/lib.rs"));
+        assert!(matches("src/*", "src/lib.rs"));
+        assert!(!matches("src/*", "src/a/b.rs"));
+    }
+
+    #[test]
+    fn globstar_crosses_segments() {
+        assert!(matches("**", "a/b/c"));
+        assert!(matches("src/**/*.rs", "src/lib.rs"));
+        assert!(matches("src/**/*.rs", "src/a/b/lib.rs"));
+        assert!(!matches("src/**/*.rs", "tests/lib.rs"));
+        assert!(matches("a/**", "a/b/c"));
+        assert!(matches("**/target", "target"));
+        assert!(matches("**/target", "a/b/target"));
+    }
+
+    #[test]
+    fn question_marks_and_classes() {
+        assert!(matches("?.txt", "a.txt"));
+        assert!(!matches("?.txt", "ab.txt"));
+        assert!(!matches("a?b", "a/b"));
+        assert!(matches("[a-c]x", "bx"));
+        assert!(!matches("[a-c]x", "dx"));
+        assert!(matches("[!a-c]x", "dx"));
+        assert!(matches("[^a-c]x", "dx"));
+        assert!(matches("[]]", "]"));
+        assert!(Glob::new("[abc").is_err());
+    }
+
+    #[test]
+    fn brace_alternation() {
+        assert!(matches("*.{rs,toml}", "Cargo.toml"));
+        assert!(matches("*.{rs,toml}", "lib.rs"));
+        assert!(!matches("*.{rs,toml}", "README.md"));
+        assert!(matches("{src,tests}/**/*.rs", "tests/a/b.rs"));
+        assert!(matches("a{b,c{d,e}}f", "acef"));
+        assert!(matches("{a,b}{c,d}", "bd"));
+        assert!(matches("x{,.bak}", "x"));
+        assert!(matches("[{]", "{"));
+        assert!(Glob::new("{a,b").is_err());
+    }
+
+    #[test]
+    fn literal_len_ranks_specificity() {
+        assert!(
+            Glob::new("src/**").unwrap().literal_len() > Glob::new("**").unwrap().literal_len()
+        );
+        assert_eq!(Glob::new("{a,bcd}").unwrap().literal_len(), 1);
+    }
+}
@@ -0,0 +1,127 @@
+//! Content hashes: the `hash` operation and compare-and-swap writes.
+
+use crate::bindings::ntwk::theater::filesystem;
+use crate::logging::log;
+use crate::ops::path_exists;
+use crate::permissions::PermissionRule;
+use crate::response::{ErrorCode, FsError, FsResult};
+use crate::walk::{walk, Visit};
+use serde::Serialize;
+use serde_json::json;
+use sha1::{Digest, Sha1};
+use sha2::Sha256;
+
+#[derive(Debug, Serialize)]
+struct FileHash {
+    path: String,
+    size: usize,
+    sha1: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sha256: Option<String>,
+}
+
+/// Hashes a file or, with `recursive`, every readable file below a
+/// directory, so changes can be detected without transferring contents.
+pub fn hash(path: &str, sha256: bool, recursive: bool, rules: &[PermissionRule]) -> FsResult {
+    log(&format!("Hashing: {}", path));
+    if !recursive {
+        let entry = hash_file(path, sha256)?;
+        return Ok(Some(json!(entry)));
+    }
+
+    let mut files = Vec::new();
+    let mut error = None;
+    walk(path, rules, None, |entry| {
+        if entry.is_dir {
+            return Visit::Continue;
+        }
+        match hash_file(entry.path, sha256) {
+            Ok(file) => {
+                files.push(file);
+                Visit::Continue
+            }
+            Err(e) => {
+                error = Some(e);
+                Visit::Stop
+            }
+        }
+    })?;
+    if let Some(error) = error {
+        return Err(error);
+    }
+
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(Some(json!({
+        "path": path,
+        "count": files.len(),
+        "files": files,
+    })))
+}
+
+fn hash_file(path: &str, sha256: bool) -> Result<FileHash, FsError> {
+    let content =
+        filesystem::read_file(path).map_err(|e| FsError::host("Failed to read file", e))?;
+    Ok(FileHash {
+        path: path.to_string(),
+        size: content.len(),
+        sha1: sha1_hex(&content),
+        sha256: sha256.then(|| sha256_hex(&content)),
+    })
+}
+
+/// Hex-encoded SHA-1 of `bytes`.
+pub fn sha1_hex(bytes: &[u8]) -> String {
+    format!("{:x}", Sha1::digest(bytes))
+}
+
+/// Hex-encoded SHA-256 of `bytes`.
+pub fn sha256_hex(bytes: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(bytes))
+}
+
+/// Fails with `conflict` unless the file at `path` currently hashes to
+/// `expected`. A missing file never matches.
+pub fn check_expected(path: &str, expected: Option<&str>) -> Result<(), FsError> {
+    let Some(expected) = expected else {
+        return Ok(());
+    };
+    let current = if path_exists(path)? {
+        let content =
+            filesystem::read_file(path).map_err(|e| FsError::host("Failed to read file", e))?;
+        Some(sha1_hex(&content))
+    } else {
+        None
+    };
+    if current
+        .as_deref()
+        .is_some_and(|current| current.eq_ignore_ascii_case(expected))
+    {
+        return Ok(());
+    }
+    Err(FsError::new(
+        ErrorCode::Conflict,
+        match &current {
+            Some(current) => format!(
+                "'{}' has changed: expected hash {}, found {}",
+                path, expected, current
+            ),
+            None => format!("'{}' no longer exists", path),
+        },
+    )
+    .with_data(json!({ "current_hash": current })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{sha1_hex, sha256_hex};
+
+    #[test]
+    fn digests_are_lowercase_hex() {
+        assert_eq!(sha1_hex(b""), "da39a3ee5e6b4b0d3255bfef95601890afd80709");
+        assert_eq!(sha1_hex(b"abc"), "a9993e364706816aba3e25717850c26c9cd0d89d");
+        assert_eq!(
+            sha256_hex(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+}
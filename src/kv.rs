@@ -0,0 +1,162 @@
+//! A JSON key-value store kept under `data/`.
+//!
+//! Each key is stored in its own file, `data/<encoded key>.json`. Keys are
+//! percent-encoded so any string maps to a single safe file name.
+
+use crate::bindings::ntwk::theater::filesystem;
+use crate::logging::log;
+use crate::ops::path_exists;
+use crate::path::join;
+use crate::permissions::{self, Permission, PermissionRule};
+use crate::response::{ErrorCode, FsError, FsResult};
+use serde_json::{json, Value};
+use std::fmt::Write;
+
+pub const DATA_DIR: &str = "data";
+const EXTENSION: &str = ".json";
+/// Keeps encoded file names well inside common file system limits.
+const MAX_ENCODED_KEY_LENGTH: usize = 200;
+
+/// The file a key is stored in.
+pub fn key_path(key: &str) -> String {
+    format!("{}/{}{}", DATA_DIR, encode_key(key), EXTENSION)
+}
+
+pub fn put(key: &str, value: &Value) -> FsResult {
+    log(&format!("Putting key: {}", key));
+    let path = checked_path(key)?;
+    if !path_exists(DATA_DIR)? {
+        filesystem::create_dir(DATA_DIR)
+            .map_err(|e| FsError::host("Failed to create data directory", e))?;
+    }
+    let created = !path_exists(&path)?;
+    filesystem::write_file(&path, &value.to_string())
+        .map_err(|e| FsError::host("Failed to write value", e))?;
+    Ok(Some(json!({ "key": key, "created": created })))
+}
+
+pub fn get(key: &str) -> FsResult {
+    log(&format!("Getting key: {}", key));
+    let path = checked_path(key)?;
+    if !path_exists(&path)? {
+        return Err(not_found(key));
+    }
+    let content =
+        filesystem::read_file(&path).map_err(|e| FsError::host("Failed to read value", e))?;
+    let value: Value = serde_json::from_slice(&content).map_err(|e| {
+        FsError::new(
+            ErrorCode::HostError,
+            format!("Stored value for key '{}' is not valid JSON: {}", key, e),
+        )
+    })?;
+    Ok(Some(json!({ "key": key, "value": value })))
+}
+
+pub fn delete(key: &str) -> FsResult {
+    log(&format!("Deleting key: {}", key));
+    let path = checked_path(key)?;
+    if !path_exists(&path)? {
+        return Err(not_found(key));
+    }
+    filesystem::delete_file(&path).map_err(|e| FsError::host("Failed to delete value", e))?;
+    Ok(None)
+}
+
+/// Lists the keys starting with `prefix` whose files the rules allow
+/// reading, in sorted order.
+pub fn list(prefix: &str, rules: &[PermissionRule]) -> FsResult {
+    log(&format!("Listing keys with prefix: {}", prefix));
+    let names = if path_exists(DATA_DIR)? {
+        filesystem::list_files(DATA_DIR).map_err(|e| FsError::host("Failed to list keys", e))?
+    } else {
+        Vec::new()
+    };
+    let mut keys: Vec<String> = names
+        .iter()
+        .filter(|name| permissions::check(rules, Permission::Read, &join(DATA_DIR, name)).is_ok())
+        .filter_map(|name| decode_key(name.strip_suffix(EXTENSION)?))
+        .filter(|key| key.starts_with(prefix))
+        .collect();
+    keys.sort();
+    Ok(Some(json!({ "count": keys.len(), "keys": keys })))
+}
+
+fn checked_path(key: &str) -> Result<String, FsError> {
+    if key.is_empty() {
+        return Err(FsError::new(
+            ErrorCode::InvalidRequest,
+            "key must not be empty",
+        ));
+    }
+    let encoded = encode_key(key);
+    if encoded.len() > MAX_ENCODED_KEY_LENGTH {
+        return Err(FsError::new(
+            ErrorCode::InvalidRequest,
+            format!(
+                "key is too long ({} bytes once encoded, at most {})",
+                encoded.len(),
+                MAX_ENCODED_KEY_LENGTH
+            ),
+        ));
+    }
+    Ok(format!("{}/{}{}", DATA_DIR, encoded, EXTENSION))
+}
+
+fn not_found(key: &str) -> FsError {
+    FsError::new(ErrorCode::NotFound, format!("No value for key '{}'", key))
+}
+
+/// Percent-encodes every byte other than ASCII letters, digits, `-` and `_`,
+/// so keys can't contain separators or be `.`/`..`.
+fn encode_key(key: &str) -> String {
+    let mut encoded = String::with_capacity(key.len());
+    for byte in key.bytes() {
+        if byte.is_ascii_alphanumeric() || byte == b'-' || byte == b'_' {
+            encoded.push(byte as char);
+        } else {
+            let _ = write!(encoded, "%{:02X}", byte);
+        }
+    }
+    encoded
+}
+
+/// Reverses `encode_key`, ignoring names it could not have produced.
+fn decode_key(name: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(name.len());
+    let mut chars = name.bytes();
+    while let Some(byte) = chars.next() {
+        match byte {
+            b'%' => {
+                let hex = [chars.next()?, chars.next()?];
+                bytes.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+            }
+            byte if byte.is_ascii_alphanumeric() || byte == b'-' || byte == b'_' => {
+                bytes.push(byte)
+            }
+            _ => return None,
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_key, encode_key, key_path};
+
+    #[test]
+    fn keys_encode_to_safe_file_names() {
+        assert_eq!(key_path("user-1_a"), "data/user-1_a.json");
+        assert_eq!(encode_key("a/b"), "a%2Fb");
+        assert_eq!(encode_key(".."), "%2E%2E");
+        assert_eq!(encode_key("é"), "%C3%A9");
+    }
+
+    #[test]
+    fn keys_round_trip() {
+        for key in ["plain", "with space/and slash", "..", "ünïcødé", "50%"] {
+            assert_eq!(decode_key(&encode_key(key)).as_deref(), Some(key));
+        }
+        assert_eq!(decode_key("bad.name"), None);
+        assert_eq!(decode_key("%G0"), None);
+    }
+}
@@ -1,39 +1,115 @@
+mod batch;
+#[allow(static_mut_refs)]
 mod bindings;
+mod blobs;
+mod copy;
+mod diff;
+mod edit;
+mod glob;
+mod hash;
+mod kv;
+mod logging;
+mod ops;
+mod patch;
+mod path;
+mod permissions;
+mod read;
+mod request;
+mod response;
+mod search;
+mod snapshot;
+mod tree;
+mod walk;
 
 use bindings::exports::ntwk::theater::actor::Guest as ActorGuest;
 use bindings::exports::ntwk::theater::message_server_client::Guest as MessageServerClient;
-use bindings::ntwk::theater::filesystem::{
-    create_dir, delete_dir, delete_file, list_files, read_file, write_file,
-};
-use bindings::ntwk::theater::runtime::log;
 use bindings::ntwk::theater::types::Json;
+use copy::CopyOptions;
+use logging::log;
+use permissions::{Permission, PermissionRule};
+use request::FsRequest;
+use response::{ErrorCode, FsError, FsResponse, FsResult};
+use search::SearchOptions;
 use serde::{Deserialize, Serialize};
-use serde_json::json;
+use std::collections::BTreeMap;
 
 #[derive(Debug, Serialize, Deserialize)]
 struct InitData {
+    /// Legacy global permissions ("read", "write", "delete") applied to
+    /// every path.
+    #[serde(default)]
     permissions: Vec<String>,
+    #[serde(default)]
+    rules: Vec<PermissionRule>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 struct State {
+    #[serde(default)]
+    rules: Vec<PermissionRule>,
+    /// Global permissions from state saved before rules existed; turned
+    /// into a rule by `State::load`.
+    #[serde(default, skip_serializing)]
     permissions: Vec<String>,
+    /// Counter for ids assigned to requests that didn't supply one.
+    #[serde(default)]
+    next_request_id: u64,
+    /// File sizes computed by `tree`, dropped whenever a request modifies
+    /// the file. Bounded, and stale if the file is changed outside this
+    /// actor.
+    #[serde(default)]
+    size_cache: BTreeMap<String, u64>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct FsRequest {
-    operation: String,
-    path: String,
-    content: Option<String>,
-    old_text: Option<String>,
-    new_text: Option<String>
+impl State {
+    fn load(state: &[u8]) -> Self {
+        let mut state: State = serde_json::from_slice(state).unwrap();
+        let legacy = std::mem::take(&mut state.permissions);
+        state.rules.extend(global_rule(&legacy));
+        state
+    }
+
+    /// Returns the client-supplied `id` of `message`, unchanged, if it is
+    /// a string or number, or a fresh one.
+    fn request_id(&mut self, message: Option<&serde_json::Value>) -> serde_json::Value {
+        match message.and_then(|m| m.get("id")) {
+            Some(id @ (serde_json::Value::String(_) | serde_json::Value::Number(_))) => id.clone(),
+            _ => {
+                self.next_request_id += 1;
+                serde_json::Value::String(format!("req-{}", self.next_request_id))
+            }
+        }
+    }
+
+    fn check(&self, permission: Permission, path: &str) -> Result<(), FsError> {
+        permissions::check(&self.rules, permission, path).map_err(|e| {
+            log(&e);
+            FsError::new(ErrorCode::PermissionDenied, e)
+        })
+    }
+
+    /// Drops cached data for `path` and everything below it.
+    fn invalidate(&mut self, path: &str) {
+        if path == "." {
+            self.size_cache.clear();
+            return;
+        }
+        let prefix = format!("{}/", path);
+        self.size_cache
+            .retain(|cached, _| cached != path && !cached.starts_with(&prefix));
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct FsResponse {
-    success: bool,
-    data: Option<serde_json::Value>,
-    error: Option<String>,
+/// The rule for a legacy global `permissions` list, if it grants anything.
+fn global_rule(permissions: &[String]) -> Option<PermissionRule> {
+    let mut global = Vec::new();
+    for permission in permissions {
+        match Permission::parse(permission) {
+            Some(permission) => global.push(permission),
+            None => log(&format!("Ignoring unknown permission: {}", permission)),
+        }
+    }
+    (!global.is_empty()).then(|| PermissionRule::global(global))
 }
 
 struct Component;
@@ -44,17 +120,31 @@ impl ActorGuest for Component {
         let init_data: InitData = if let Some(data) = data {
             serde_json::from_slice(&data).unwrap_or(InitData {
                 permissions: vec!["read".to_string()],
+                rules: Vec::new(),
             })
         } else {
             InitData {
                 permissions: vec!["read".to_string()],
+                rules: Vec::new(),
             }
         };
 
         log(&format!("Permissions: {:?}", init_data.permissions));
+        log(&format!("Rules: {:?}", init_data.rules));
+
+        let mut rules = init_data.rules;
+        if let Err(e) = permissions::validate(&rules) {
+            log(&format!("{}; denying all operations", e));
+            rules.clear();
+        } else {
+            rules.extend(global_rule(&init_data.permissions));
+        }
 
         let state = State {
-            permissions: init_data.permissions,
+            rules,
+            permissions: Vec::new(),
+            next_request_id: 0,
+            size_cache: BTreeMap::new(),
         };
         serde_json::to_vec(&state).unwrap()
     }
@@ -62,235 +152,24 @@ impl ActorGuest for Component {
 
 impl MessageServerClient for Component {
     fn handle_request(message: Json, state: Json) -> (Json, Json) {
+        let mut state = State::load(&state);
+        let parsed: Result<serde_json::Value, _> = serde_json::from_slice(&message);
+        let id = state.request_id(parsed.as_ref().ok());
+        let _scope = logging::request_scope(&id);
         log("Handling request");
         log(&format!("Message: {:?}", message));
-        let state: State = serde_json::from_slice(&state).unwrap();
-        let request: FsRequest = match serde_json::from_slice(&message) {
-            Ok(req) => req,
-            Err(e) => {
-                let response = FsResponse {
-                    success: false,
-                    data: None,
-                    error: Some(format!("Invalid request format: {}", e)),
-                };
-                return (
-                    serde_json::to_vec(&response).unwrap(),
-                    serde_json::to_vec(&state).unwrap(),
-                );
-            }
-        };
 
-        // Handle operations that need responses
-        let response = match request.operation.as_str() {
-            "read-file" => {
-                log(&format!("Reading file: {}", request.path));
-                if !state.permissions.contains(&"read".to_string()) {
-                    log("Read permission denied");
-                    FsResponse {
-                        success: false,
-                        data: None,
-                        error: Some("Read permission denied".to_string()),
-                    }
-                } else {
-                    match read_file(&request.path) {
-                        Ok(content) => {
-                            log(&format!("Read file: {}", request.path));
-                            let content_str = String::from_utf8_lossy(&content).to_string();
-                            FsResponse {
-                                success: true,
-                                data: Some(json!(content_str)),
-                                error: None,
-                            }
-                        }
-                        Err(e) => FsResponse {
-                            success: false,
-                            data: None,
-                            error: Some(format!("Failed to read file: {}", e)),
-                        },
-                    }
-                }
-            }
-            "list-files" => {
-                log(&format!("Listing files in: {}", request.path));
-                if !state.permissions.contains(&"read".to_string()) {
-                    log("Read permission denied");
-                    FsResponse {
-                        success: false,
-                        data: None,
-                        error: Some("Read permission denied".to_string()),
-                    }
-                } else {
-                    match list_files(&request.path) {
-                        Ok(files) => FsResponse {
-                            success: true,
-                            data: Some(json!(files)),
-                            error: None,
-                        },
-                        Err(e) => FsResponse {
-                            success: false,
-                            data: None,
-                            error: Some(format!("Failed to list files: {}", e)),
-                        },
-                    }
-                }
-            }
-            "write-file" => {
-                log(&format!("Writing file: {}", request.path));
-                if !state.permissions.contains(&"write".to_string()) {
-                    log("Write permission denied");
-                    FsResponse {
-                        success: false,
-                        data: None,
-                        error: Some("Write permission denied".to_string()),
-                    }
-                } else {
-                    if let Some(content) = request.content {
-                        log("Checks passed");
-                        match write_file(&request.path, &content) {
-                            Ok(_) => FsResponse {
-                                success: true,
-                                data: None,
-                                error: None,
-                            },
-                            Err(e) => FsResponse {
-                                success: false,
-                                data: None,
-                                error: Some(format!("Failed to write file: {}", e)),
-                            },
-                        }
-                    } else {
-                        FsResponse {
-                            success: false,
-                            data: None,
-                            error: Some("Content not provided".to_string()),
-                        }
-                    }
-                }
-            }
-            "create-dir" => {
-                log(&format!("Creating directory: {}", request.path));
-                if !state.permissions.contains(&"write".to_string()) {
-                    log("Write permission denied");
-                    FsResponse {
-                        success: false,
-                        data: None,
-                        error: Some("Write permission denied".to_string()),
-                    }
-                } else {
-                    match create_dir(&request.path) {
-                        Ok(_) => FsResponse {
-                            success: true,
-                            data: None,
-                            error: None,
-                        },
-                        Err(e) => FsResponse {
-                            success: false,
-                            data: None,
-                            error: Some(format!("Failed to create directory: {}", e)),
-                        },
-                    }
-                }
-            }
-            "delete-dir" => {
-                log(&format!("Deleting directory: {}", request.path));
-                if !state.permissions.contains(&"delete".to_string()) {
-                    log("Delete permission denied");
-                    FsResponse {
-                        success: false,
-                        data: None,
-                        error: Some("Delete permission denied".to_string()),
-                    }
-                } else {
-                    match delete_dir(&request.path) {
-                        Ok(_) => FsResponse {
-                            success: true,
-                            data: None,
-                            error: None,
-                        },
-                        Err(e) => FsResponse {
-                            success: false,
-                            data: None,
-                            error: Some(format!("Failed to delete directory: {}", e)),
-                        },
-                    }
-                }
-            }
-            "delete-file" => {
-                log(&format!("Deleting file: {}", request.path));
-                if !state.permissions.contains(&"delete".to_string()) {
-                    log("Delete permission denied");
-                    FsResponse {
-                        success: false,
-                        data: None,
-                        error: Some("Delete permission denied".to_string()),
-                    }
-                } else {
-                    match delete_file(&request.path) {
-                        Ok(_) => FsResponse {
-                            success: true,
-                            data: None,
-                            error: None,
-                        },
-                        Err(e) => FsResponse {
-                            success: false,
-                            data: None,
-                            error: Some(format!("Failed to delete file: {}", e)),
-                        },
-                    }
-                }
-            }
-            "edit-file" => {
-                log(&format!("Editing file: {}", request.path));
-                if !state.permissions.contains(&"write".to_string()) {
-                    log("Write permission denied");
-                    FsResponse {
-                        success: false,
-                        data: None,
-                        error: Some("Write permission denied".to_string()),
-                    }
-                } else {
-                    match read_file(&request.path) {
-                        Ok(content) => {
-                            let mut content_str = String::from_utf8_lossy(&content).to_string();
-                            if let (Some(old_text), Some(new_text)) = (request.old_text, request.new_text) {
-                                content_str = content_str.replace(&old_text, &new_text);
-                                match write_file(&request.path, &content_str) {
-                                    Ok(_) => FsResponse {
-                                        success: true,
-                                        data: None,
-                                        error: None,
-                                    },
-                                    Err(e) => FsResponse {
-                                        success: false,
-                                        data: None,
-                                        error: Some(format!("Failed to write edited file: {}", e)),
-                                    },
-                                }
-                            } else {
-                                FsResponse {
-                                    success: false,
-                                    data: None,
-                                    error: Some("Both old_text and new_text must be provided".to_string()),
-                                }
-                            }
-                        },
-                        Err(e) => FsResponse {
-                            success: false,
-                            data: None,
-                            error: Some(format!("Failed to read file for editing: {}", e)),
-                        },
-                    }
-                }
-            },
-            _ => {
-                log("Operation not supported");
-                FsResponse {
-                    success: false,
-                    data: None,
-                    error: Some("Operation not supported for request type".to_string()),
-                }
+        let mut response = match parsed
+            .map_err(|e| FsError::new(ErrorCode::InvalidRequest, format!("Invalid JSON: {}", e)))
+            .and_then(parse_request)
+        {
+            Ok(request) => FsResponse::from(handle(request, &mut state)),
+            Err(e) => {
+                log(&e.message);
+                FsResponse::from(e)
             }
         };
+        response.id = Some(id);
 
         (
             serde_json::to_vec(&response).unwrap(),
@@ -299,23 +178,213 @@ impl MessageServerClient for Component {
     }
 
     fn handle_send(message: Json, state: Json) -> Json {
+        let mut state = State::load(&state);
+        let parsed: Option<serde_json::Value> = serde_json::from_slice(&message).ok();
+        let id = state.request_id(parsed.as_ref());
+        let _scope = logging::request_scope(&id);
         log("Handling send");
         log(&format!("Message: {:?}", message));
-        let state: State = serde_json::from_slice(&state).unwrap();
-        let request: FsRequest = match serde_json::from_slice(&message) {
-            Ok(req) => req,
-            Err(_) => return serde_json::to_vec(&state).unwrap(),
-        };
-
-        // Handle operations that don't need responses
-        match request.operation.as_str() {
-            _ => {
-                log("Send messages not supported");
-            }
+        if parsed.map(parse_request).is_some_and(|r| r.is_ok()) {
+            // There are no operations that don't need responses
+            log("Send messages not supported");
         }
 
         serde_json::to_vec(&state).unwrap()
     }
 }
 
+fn parse_request(message: serde_json::Value) -> Result<FsRequest, FsError> {
+    if let Some(operation) = message.get("operation").and_then(|op| op.as_str()) {
+        if !request::OPERATIONS.contains(&operation) {
+            return Err(FsError::new(
+                ErrorCode::UnsupportedOperation,
+                format!("Unsupported operation: {}", operation),
+            ));
+        }
+    }
+    FsRequest::deserialize(message).map_err(|e| {
+        FsError::new(
+            ErrorCode::InvalidRequest,
+            format!("Invalid request format: {}", e),
+        )
+    })
+}
+
+fn handle(mut request: FsRequest, state: &mut State) -> FsResult {
+    validate(&mut request, state)?;
+    execute(request, state)
+}
+
+/// Normalizes the request's paths in place and checks them against the
+/// permission rules.
+fn validate(request: &mut FsRequest, state: &State) -> Result<(), FsError> {
+    for path in request.paths_mut() {
+        *path = path::normalize(path).map_err(|e| {
+            log(&format!("Invalid path: {}", e));
+            FsError::new(ErrorCode::InvalidPath, e)
+        })?;
+    }
+
+    for (permission, path) in request.access() {
+        state.check(permission, &path)?;
+    }
+
+    Ok(())
+}
+
+fn execute(request: FsRequest, state: &mut State) -> FsResult {
+    for (permission, path) in request.access() {
+        if permission != Permission::Read {
+            state.invalidate(&path);
+        }
+    }
+
+    match request {
+        FsRequest::ReadFile { path, options } => read::read_file(&path, &options),
+        FsRequest::ListFiles { path } => ops::list_files(&path),
+        FsRequest::Exists { path } => ops::exists(&path),
+        FsRequest::Hash {
+            path,
+            sha256,
+            recursive,
+        } => hash::hash(&path, sha256, recursive, &state.rules),
+        FsRequest::WriteFile {
+            path,
+            content,
+            encoding,
+            expected_hash,
+        } => {
+            hash::check_expected(&path, expected_hash.as_deref())?;
+            ops::write_file(&path, &content, encoding)
+        }
+        FsRequest::Append {
+            path,
+            content,
+            ensure_newline,
+        } => ops::append(&path, &content, ensure_newline),
+        FsRequest::CreateDir { path } => ops::create_dir(&path),
+        FsRequest::DeleteFile {
+            path,
+            expected_hash,
+        } => {
+            hash::check_expected(&path, expected_hash.as_deref())?;
+            ops::delete_file(&path)
+        }
+        FsRequest::DeleteDir { path } => ops::delete_dir(&path, state),
+        FsRequest::EditFile {
+            path,
+            edit,
+            expected_hash,
+        } => {
+            hash::check_expected(&path, expected_hash.as_deref())?;
+            edit::edit_file(&path, &edit)
+        }
+        FsRequest::MultiEdit { path, edits } => edit::multi_edit(&path, &edits),
+        FsRequest::InsertLines {
+            path,
+            line,
+            position,
+            text,
+        } => edit::insert_lines(&path, line, position, &text),
+        FsRequest::DeleteLines {
+            path,
+            start_line,
+            end_line,
+        } => edit::delete_lines(&path, start_line, end_line),
+        FsRequest::Patch {
+            path,
+            patch,
+            fuzz,
+            strip,
+        } => patch::apply_patch(&path, &patch, fuzz, strip),
+        FsRequest::Diff {
+            path,
+            other,
+            content,
+            context,
+        } => diff::diff(&path, other.as_deref(), content.as_deref(), context),
+        FsRequest::Move {
+            source,
+            destination,
+            overwrite,
+        } => ops::move_path(&source, &destination, overwrite, state),
+        FsRequest::Copy {
+            source,
+            destination,
+            overwrite,
+            skip_existing,
+            max_bytes,
+        } => copy::copy(
+            &source,
+            &destination,
+            &CopyOptions {
+                overwrite,
+                skip_existing,
+                max_bytes,
+            },
+            state,
+        ),
+        FsRequest::Tree {
+            path,
+            max_depth,
+            include,
+            exclude,
+        } => tree::tree(&path, max_depth, &include, &exclude, state),
+        FsRequest::Glob {
+            path,
+            pattern,
+            max_results,
+        } => search::glob(&path, &pattern, max_results, &state.rules),
+        FsRequest::Search {
+            path,
+            pattern,
+            regex,
+            case_sensitive,
+            include,
+            exclude,
+            context,
+            max_matches,
+        } => search::search(
+            &path,
+            &pattern,
+            &SearchOptions {
+                regex,
+                case_sensitive,
+                include,
+                exclude,
+                context,
+                max_matches,
+            },
+            &state.rules,
+        ),
+        FsRequest::StoreContent { content, encoding } => blobs::store(&content, encoding, state),
+        FsRequest::GetContent { hash, encoding } => blobs::get(&hash, encoding),
+        FsRequest::Put { key, value } => kv::put(&key, &value),
+        FsRequest::Get { key } => kv::get(&key),
+        FsRequest::Delete { key } => kv::delete(&key),
+        FsRequest::ListKeys { prefix } => kv::list(&prefix, &state.rules),
+        FsRequest::Batch { requests, atomic } => batch::run(requests, atomic, state),
+    }
+}
+
 bindings::export!(Component with_types_in bindings);
+
+#[cfg(test)]
+mod tests {
+    use super::State;
+    use serde_json::json;
+
+    #[test]
+    fn request_ids_are_echoed_or_generated() {
+        let mut state = State::load(b"{}");
+        let mut id = |message: serde_json::Value| state.request_id(Some(&message));
+        assert_eq!(id(json!({ "id": "abc" })), json!("abc"));
+        assert_eq!(id(json!({ "id": 5 })), json!(5));
+        assert_eq!(id(json!({})), json!("req-1"));
+        assert_eq!(id(json!({ "id": null })), json!("req-2"));
+        assert_eq!(id(json!({ "id": ["x"] })), json!("req-3"));
+        assert_eq!(id(json!({ "id": { "a": 1 } })), json!("req-4"));
+        assert_eq!(state.request_id(None), json!("req-5"));
+        assert_eq!(state.next_request_id, 5);
+    }
+}
@@ -0,0 +1,34 @@
+//! Logging tagged with the id of the request being served.
+
+use crate::bindings::ntwk::theater::runtime;
+use std::cell::RefCell;
+
+thread_local! {
+    static REQUEST_ID: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Logs `msg`, prefixed with the current request id if there is one.
+pub fn log(msg: &str) {
+    REQUEST_ID.with(|id| match &*id.borrow() {
+        Some(id) => runtime::log(&format!("[{}] {}", id, msg)),
+        None => runtime::log(msg),
+    })
+}
+
+/// Tags every `log` call with `id` until the returned guard is dropped.
+pub fn request_scope(id: &serde_json::Value) -> RequestScope {
+    let id = match id {
+        serde_json::Value::String(id) => id.clone(),
+        id => id.to_string(),
+    };
+    REQUEST_ID.with(|current| *current.borrow_mut() = Some(id));
+    RequestScope
+}
+
+pub struct RequestScope;
+
+impl Drop for RequestScope {
+    fn drop(&mut self) {
+        REQUEST_ID.with(|current| *current.borrow_mut() = None);
+    }
+}
@@ -0,0 +1,208 @@
+use crate::bindings::ntwk::theater::filesystem;
+use crate::logging::log;
+use crate::path::join;
+use crate::permissions::Permission;
+use crate::request::Encoding;
+use crate::response::{ErrorCode, FsError, FsResult};
+use crate::snapshot::{remove, restore, snapshot, Snapshot};
+use crate::State;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde_json::json;
+use std::borrow::Cow;
+
+pub fn list_files(path: &str) -> FsResult {
+    log(&format!("Listing files in: {}", path));
+    let files =
+        filesystem::list_files(path).map_err(|e| FsError::host("Failed to list files", e))?;
+    Ok(Some(json!(files)))
+}
+
+pub fn exists(path: &str) -> FsResult {
+    log(&format!("Checking existence of: {}", path));
+    let exists = path_exists(path)?;
+    let kind = if !exists {
+        None
+    } else if is_dir(path) {
+        Some("directory")
+    } else {
+        Some("file")
+    };
+    Ok(Some(json!({ "exists": exists, "kind": kind })))
+}
+
+/// Writes `content` to the file. The host only writes text, so base64
+/// content must decode to valid UTF-8.
+pub fn write_file(path: &str, content: &str, encoding: Encoding) -> FsResult {
+    log(&format!("Writing file: {}", path));
+    let content = decode_text(content, encoding)?;
+    filesystem::write_file(path, &content).map_err(|e| FsError::host("Failed to write file", e))?;
+    Ok(None)
+}
+
+/// Decodes request content into the text the host can write.
+pub fn decode_text(content: &str, encoding: Encoding) -> Result<Cow<'_, str>, FsError> {
+    match encoding {
+        Encoding::Utf8 => Ok(Cow::Borrowed(content)),
+        Encoding::Base64 => {
+            let bytes = BASE64.decode(content).map_err(|e| {
+                FsError::new(
+                    ErrorCode::InvalidRequest,
+                    format!("Invalid base64 content: {}", e),
+                )
+            })?;
+            String::from_utf8(bytes).map(Cow::Owned).map_err(|_| {
+                FsError::new(
+                    ErrorCode::UnsupportedOperation,
+                    "Decoded content is not valid UTF-8 and the host can only write text files",
+                )
+            })
+        }
+    }
+}
+
+/// Appends `content` to the file, creating it if needed. The read and the
+/// write happen within one request, so appends are serialized by the actor.
+/// With `ensure_newline`, the content starts on a line of its own and ends
+/// with a newline.
+pub fn append(path: &str, content: &str, ensure_newline: bool) -> FsResult {
+    log(&format!("Appending to file: {}", path));
+    let exists = path_exists(path)?;
+    let mut existing = if exists {
+        read_text(path)?
+    } else {
+        String::new()
+    };
+
+    let before = existing.len();
+    if ensure_newline && !existing.is_empty() && !existing.ends_with('\n') {
+        existing.push('\n');
+    }
+    existing.push_str(content);
+    if ensure_newline && !existing.ends_with('\n') {
+        existing.push('\n');
+    }
+    filesystem::write_file(path, &existing)
+        .map_err(|e| FsError::host("Failed to write file", e))?;
+    Ok(Some(json!({
+        "created": !exists,
+        "bytes_appended": existing.len() - before,
+        "size": existing.len(),
+    })))
+}
+
+pub fn create_dir(path: &str) -> FsResult {
+    log(&format!("Creating directory: {}", path));
+    filesystem::create_dir(path).map_err(|e| FsError::host("Failed to create directory", e))?;
+    Ok(None)
+}
+
+/// Deletes a directory and everything in it. The host deletes
+/// recursively, so every entry below `path` must allow deleting.
+pub fn delete_dir(path: &str, state: &State) -> FsResult {
+    log(&format!("Deleting directory: {}", path));
+    check_descendants(path, Permission::Delete, state)?;
+    filesystem::delete_dir(path).map_err(|e| FsError::host("Failed to delete directory", e))?;
+    Ok(None)
+}
+
+/// Checks `permission` on every entry below `path`. Only names are listed,
+/// so this works on trees containing binary files.
+pub fn check_descendants(path: &str, permission: Permission, state: &State) -> Result<(), FsError> {
+    let Ok(names) = filesystem::list_files(path) else {
+        return Ok(());
+    };
+    for name in names {
+        let child = join(path, &name);
+        state.check(permission, &child)?;
+        check_descendants(&child, permission, state)?;
+    }
+    Ok(())
+}
+
+pub fn delete_file(path: &str) -> FsResult {
+    log(&format!("Deleting file: {}", path));
+    filesystem::delete_file(path).map_err(|e| FsError::host("Failed to delete file", e))?;
+    Ok(None)
+}
+
+/// Moves a file or directory tree. The host has no rename import, so the
+/// source is read into memory in full before anything is written, then
+/// written to the destination and finally deleted.
+pub fn move_path(source: &str, destination: &str, overwrite: bool, state: &State) -> FsResult {
+    log(&format!("Moving {} to {}", source, destination));
+    if source == "." || destination == "." {
+        return Err(FsError::new(
+            ErrorCode::InvalidRequest,
+            "Cannot move to or from the root directory",
+        ));
+    }
+    if destination.starts_with(&format!("{}/", source)) {
+        return Err(FsError::new(
+            ErrorCode::InvalidRequest,
+            format!("Cannot move '{}' into itself", source),
+        ));
+    }
+    if source.starts_with(&format!("{}/", destination)) {
+        return Err(FsError::new(
+            ErrorCode::InvalidRequest,
+            format!("Cannot move '{}' over its parent '{}'", source, destination),
+        ));
+    }
+
+    let tree = snapshot(source)?;
+    let kind = match &tree {
+        Snapshot::Missing => {
+            return Err(FsError::new(
+                ErrorCode::NotFound,
+                format!("Source does not exist: {}", source),
+            ))
+        }
+        Snapshot::File(_) => "file",
+        Snapshot::Dir(_) => "directory",
+    };
+    for relative in tree.descendants() {
+        state.check(Permission::Read, &join(source, &relative))?;
+        state.check(Permission::Delete, &join(source, &relative))?;
+        state.check(Permission::Write, &join(destination, &relative))?;
+    }
+    let result = json!({ "source": source, "destination": destination, "kind": kind });
+    if source == destination {
+        return Ok(Some(result));
+    }
+
+    if path_exists(destination)? {
+        if !overwrite {
+            return Err(FsError::new(
+                ErrorCode::Conflict,
+                format!("Destination already exists: {}", destination),
+            ));
+        }
+        state.check(Permission::Delete, destination)?;
+        check_descendants(destination, Permission::Delete, state)?;
+        remove(destination).map_err(|e| FsError::host("Failed to replace destination", e))?;
+    }
+
+    restore(destination, tree).map_err(|e| FsError::host("Failed to write destination", e))?;
+    remove(source).map_err(|e| FsError::host("Failed to remove source", e))?;
+    Ok(Some(result))
+}
+
+/// The host has no stat import, so a path that can be listed is taken to be
+/// a directory.
+pub fn is_dir(path: &str) -> bool {
+    filesystem::list_files(path).is_ok()
+}
+
+/// Whether `path` exists, failing the request if the host can't tell.
+pub fn path_exists(path: &str) -> Result<bool, FsError> {
+    filesystem::path_exists(path).map_err(|e| FsError::host("Failed to check path", e))
+}
+
+/// Reads a file as text. The host can only write text, so files that are
+/// edited or written back must be valid UTF-8.
+pub fn read_text(path: &str) -> Result<String, FsError> {
+    let content =
+        filesystem::read_file(path).map_err(|e| FsError::host("Failed to read file", e))?;
+    String::from_utf8(content).map_err(|_| FsError::not_text(path))
+}
@@ -0,0 +1,531 @@
+//! Applying unified diffs (`diff -u`, `git diff`).
+//!
+//! Every file in the patch is patched in memory first; nothing is written
+//! unless every hunk of every file applies.
+
+use crate::bindings::ntwk::theater::filesystem;
+use crate::logging::log;
+use crate::ops::read_text;
+use crate::path::{self, join};
+use crate::permissions::Permission;
+use crate::response::{ErrorCode, FsError, FsResult};
+use serde::Serialize;
+use serde_json::json;
+
+#[derive(Debug, PartialEq)]
+enum Line {
+    Context(String),
+    Remove(String),
+    Add(String),
+}
+
+#[derive(Debug)]
+struct Hunk {
+    old_start: usize,
+    old_len: usize,
+    lines: Vec<Line>,
+    /// The last old/new line has a "\ No newline at end of file" marker.
+    old_no_newline: bool,
+    new_no_newline: bool,
+}
+
+#[derive(Debug)]
+struct FilePatch {
+    /// `None` for `/dev/null`, i.e. a created or deleted file.
+    old_path: Option<String>,
+    new_path: Option<String>,
+    hunks: Vec<Hunk>,
+}
+
+#[derive(Debug, Serialize)]
+struct HunkReport {
+    hunk: usize,
+    applied: bool,
+    /// How many lines away from its stated position the hunk applied.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    offset: Option<isize>,
+    /// Context lines ignored at each end of the hunk to make it apply.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fuzz: Option<usize>,
+}
+
+/// A file's lines without their `\n` terminators.
+struct Text {
+    lines: Vec<String>,
+    trailing_newline: bool,
+}
+
+impl Text {
+    fn parse(content: &str) -> Self {
+        let mut lines: Vec<String> = content.split('\n').map(str::to_string).collect();
+        let trailing_newline = content.ends_with('\n') || content.is_empty();
+        if trailing_newline {
+            lines.pop();
+        }
+        Text {
+            lines,
+            trailing_newline,
+        }
+    }
+
+    fn render(&self) -> String {
+        let mut content = self.lines.join("\n");
+        if self.trailing_newline && !self.lines.is_empty() {
+            content.push('\n');
+        }
+        content
+    }
+}
+
+/// The access applying `patch` under `base` needs: reading each file it
+/// changes, writing each file it produces and deleting each file it renames
+/// or removes. A patch that doesn't parse needs nothing, since
+/// `apply_patch` rejects it before touching any file.
+pub fn access(base: &str, patch: &str, strip: Option<usize>) -> Vec<(Permission, String)> {
+    let mut access = Vec::new();
+    for file in parse(patch, strip).unwrap_or_default() {
+        let source = file.old_path.and_then(|p| resolve(base, &p).ok());
+        let target = file.new_path.and_then(|p| resolve(base, &p).ok());
+        if let Some(source) = &source {
+            access.push((Permission::Read, source.clone()));
+        }
+        if let Some(target) = &target {
+            access.push((Permission::Write, target.clone()));
+        }
+        if let Some(source) = source.filter(|s| Some(s) != target.as_ref()) {
+            access.push((Permission::Delete, source));
+        }
+    }
+    access
+}
+
+pub fn apply_patch(base: &str, patch: &str, fuzz: usize, strip: Option<usize>) -> FsResult {
+    log(&format!("Applying patch in: {}", base));
+    let files = parse(patch, strip)
+        .map_err(|e| FsError::new(ErrorCode::InvalidRequest, format!("Invalid patch: {}", e)))?;
+    if files.is_empty() {
+        return Err(FsError::new(
+            ErrorCode::InvalidRequest,
+            "Patch does not contain any files",
+        ));
+    }
+
+    let mut reports = Vec::new();
+    let mut writes = Vec::new();
+    let mut all_applied = true;
+    for file in &files {
+        let source = file
+            .old_path
+            .as_deref()
+            .map(|p| resolve(base, p))
+            .transpose()?;
+        let target = file
+            .new_path
+            .as_deref()
+            .map(|p| resolve(base, p))
+            .transpose()?;
+        let display = target.as_deref().or(source.as_deref()).unwrap_or_default();
+
+        let original = match &source {
+            Some(source) => Text::parse(&read_text(source)?),
+            None => {
+                let target = target.as_deref().unwrap_or_default();
+                if filesystem::path_exists(target).unwrap_or(false) {
+                    return Err(FsError::new(
+                        ErrorCode::Conflict,
+                        format!("Patch creates '{}', which already exists", target),
+                    ));
+                }
+                Text::parse("")
+            }
+        };
+
+        let (patched, hunks) = apply_hunks(&original, &file.hunks, fuzz);
+        let status = match (&source, &target) {
+            (None, _) => "created",
+            (_, None) => "deleted",
+            (Some(source), Some(target)) if source != target => "renamed",
+            _ => "modified",
+        };
+        all_applied &= patched.is_some();
+        reports.push(json!({ "path": display, "status": status, "hunks": hunks }));
+        if let Some(patched) = patched {
+            writes.push((source, target, patched));
+        }
+    }
+
+    if !all_applied {
+        return Err(FsError::new(
+            ErrorCode::Conflict,
+            "Some hunks did not apply; no files were changed",
+        )
+        .with_data(json!({ "files": reports, "applied": false })));
+    }
+
+    for (source, target, patched) in &writes {
+        if target.is_none() && !patched.lines.is_empty() {
+            return Err(FsError::new(
+                ErrorCode::Conflict,
+                format!(
+                    "Patch deletes '{}' but leaves content behind",
+                    source.as_deref().unwrap_or_default()
+                ),
+            ));
+        }
+    }
+    for (source, target, patched) in writes {
+        if let Some(target) = &target {
+            filesystem::write_file(target, &patched.render())
+                .map_err(|e| FsError::host("Failed to write patched file", e))?;
+        }
+        if let Some(source) = source.filter(|s| Some(s) != target.as_ref()) {
+            filesystem::delete_file(&source)
+                .map_err(|e| FsError::host("Failed to delete file", e))?;
+        }
+    }
+
+    Ok(Some(json!({ "files": reports, "applied": true })))
+}
+
+/// Resolves a path named in the patch against the base directory.
+fn resolve(base: &str, file: &str) -> Result<String, FsError> {
+    path::normalize(&join(base, file)).map_err(|e| FsError::new(ErrorCode::InvalidPath, e))
+}
+
+fn parse(patch: &str, strip: Option<usize>) -> Result<Vec<FilePatch>, String> {
+    let lines: Vec<&str> = patch
+        .split_inclusive('\n')
+        .map(|line| line.strip_suffix('\n').unwrap_or(line))
+        .collect();
+    let mut files: Vec<FilePatch> = Vec::new();
+    let mut i = 0;
+    while i < lines.len() {
+        let line = lines[i];
+        if let Some(old) = line.strip_prefix("--- ") {
+            let new = lines
+                .get(i + 1)
+                .and_then(|l| l.strip_prefix("+++ "))
+                .ok_or_else(|| format!("Expected '+++' after line {}", i + 1))?;
+            let (old, new) = (header_path(old), header_path(new));
+            // git-style a/ and b/ prefixes are stripped unless told otherwise
+            let strip = strip.unwrap_or_else(|| {
+                let git =
+                    |p: Option<&str>| p.is_none_or(|p| p.starts_with("a/") || p.starts_with("b/"));
+                usize::from(git(old) && git(new) && (old.is_some() || new.is_some()))
+            });
+            files.push(FilePatch {
+                old_path: old.map(|p| strip_components(p, strip)).transpose()?,
+                new_path: new.map(|p| strip_components(p, strip)).transpose()?,
+                hunks: Vec::new(),
+            });
+            i += 2;
+        } else if line.starts_with("@@ ") {
+            let file = files
+                .last_mut()
+                .ok_or_else(|| format!("Hunk before any file header on line {}", i + 1))?;
+            let (hunk, next) = parse_hunk(&lines, i)?;
+            file.hunks.push(hunk);
+            i = next;
+        } else {
+            // diff --git, index, mode and other extended headers
+            i += 1;
+        }
+    }
+    Ok(files)
+}
+
+/// The path in a `---`/`+++` header, or `None` for `/dev/null`.
+fn header_path(header: &str) -> Option<&str> {
+    let path = header.split('\t').next().unwrap_or(header).trim_end();
+    (path != "/dev/null").then_some(path)
+}
+
+fn strip_components(path: &str, count: usize) -> Result<String, String> {
+    let components: Vec<&str> = path.split('/').collect();
+    if components.len() <= count {
+        return Err(format!("Cannot strip {} components from '{}'", count, path));
+    }
+    Ok(components[count..].join("/"))
+}
+
+fn parse_hunk(lines: &[&str], start: usize) -> Result<(Hunk, usize), String> {
+    let header = lines[start];
+    let invalid = || format!("Invalid hunk header on line {}: {}", start + 1, header);
+    let ranges = header
+        .strip_prefix("@@ ")
+        .and_then(|rest| rest.split(" @@").next())
+        .ok_or_else(invalid)?;
+    let (old, new) = ranges.split_once(' ').ok_or_else(invalid)?;
+    let (old_start, old_len) =
+        parse_range(old.strip_prefix('-').ok_or_else(invalid)?).ok_or_else(invalid)?;
+    let (_, new_len) =
+        parse_range(new.strip_prefix('+').ok_or_else(invalid)?).ok_or_else(invalid)?;
+
+    let mut hunk = Hunk {
+        old_start,
+        old_len,
+        lines: Vec::new(),
+        old_no_newline: false,
+        new_no_newline: false,
+    };
+    let (mut old_left, mut new_left) = (old_len, new_len);
+    let mut i = start + 1;
+    while i < lines.len() {
+        let line = lines[i];
+        if line.starts_with('\\') {
+            match hunk.lines.last() {
+                Some(Line::Remove(_)) => hunk.old_no_newline = true,
+                Some(Line::Add(_)) => hunk.new_no_newline = true,
+                Some(Line::Context(_)) => {
+                    hunk.old_no_newline = true;
+                    hunk.new_no_newline = true;
+                }
+                None => return Err(format!("Unexpected marker on line {}", i + 1)),
+            }
+            i += 1;
+            continue;
+        }
+        if old_left == 0 && new_left == 0 {
+            break;
+        }
+        // Some tools drop the space from empty context lines
+        let (kind, text) = match line.chars().next() {
+            Some(c) => (c, &line[c.len_utf8()..]),
+            None => (' ', ""),
+        };
+        match kind {
+            ' ' if old_left > 0 && new_left > 0 => {
+                old_left -= 1;
+                new_left -= 1;
+                hunk.lines.push(Line::Context(text.to_string()));
+            }
+            '-' if old_left > 0 => {
+                old_left -= 1;
+                hunk.lines.push(Line::Remove(text.to_string()));
+            }
+            '+' if new_left > 0 => {
+                new_left -= 1;
+                hunk.lines.push(Line::Add(text.to_string()));
+            }
+            _ => return Err(format!("Unexpected line {} in hunk: {}", i + 1, line)),
+        }
+        i += 1;
+    }
+    if old_left > 0 || new_left > 0 {
+        return Err(format!("Hunk starting on line {} is truncated", start + 1));
+    }
+    Ok((hunk, i))
+}
+
+/// Parses `start,len` (or just `start`, meaning a length of 1).
+fn parse_range(range: &str) -> Option<(usize, usize)> {
+    match range.split_once(',') {
+        Some((start, len)) => Some((start.parse().ok()?, len.parse().ok()?)),
+        None => Some((range.parse().ok()?, 1)),
+    }
+}
+
+/// Applies `hunks` in order, returning the patched text (if every hunk
+/// applied) and a report per hunk.
+fn apply_hunks(original: &Text, hunks: &[Hunk], fuzz: usize) -> (Option<Text>, Vec<HunkReport>) {
+    let source = &original.lines;
+    let mut lines = Vec::new();
+    let mut trailing_newline = original.trailing_newline;
+    let mut cursor = 0;
+    let mut offset: isize = 0;
+    let mut failed = false;
+    let mut reports = Vec::new();
+
+    for (index, hunk) in hunks.iter().enumerate() {
+        let old: Vec<&str> = hunk
+            .lines
+            .iter()
+            .filter_map(|line| match line {
+                Line::Context(text) | Line::Remove(text) => Some(text.as_str()),
+                Line::Add(_) => None,
+            })
+            .collect();
+        let new: Vec<&str> = hunk
+            .lines
+            .iter()
+            .filter_map(|line| match line {
+                Line::Context(text) | Line::Add(text) => Some(text.as_str()),
+                Line::Remove(_) => None,
+            })
+            .collect();
+        let leading = hunk
+            .lines
+            .iter()
+            .take_while(|line| matches!(line, Line::Context(_)))
+            .count();
+        let trailing = hunk
+            .lines
+            .iter()
+            .rev()
+            .take_while(|line| matches!(line, Line::Context(_)))
+            .count();
+        // With a zero-length old range, the start is the line to insert after
+        let stated = if hunk.old_len == 0 {
+            hunk.old_start
+        } else {
+            hunk.old_start.saturating_sub(1)
+        };
+        let expected = (stated as isize + offset).max(0) as usize;
+
+        // Past the longer run of context there is nothing left to ignore
+        let found = (0..=fuzz.min(leading.max(trailing))).find_map(|level| {
+            let lead = level.min(leading);
+            let trail = level.min(trailing).min(old.len() - lead);
+            let pattern = &old[lead..old.len() - trail];
+            find(source, pattern, cursor, expected + lead).map(|at| (at, lead, trail, level))
+        });
+        match found {
+            Some((at, lead, trail, level)) => {
+                lines.extend(source[cursor..at].iter().cloned());
+                // Fuzzed context stays as it is in the file
+                lines.extend(
+                    new[lead..new.len() - trail]
+                        .iter()
+                        .map(|line| line.to_string()),
+                );
+                cursor = at + (old.len() - lead - trail);
+                let actual_offset = at as isize - (stated + lead) as isize;
+                offset = actual_offset;
+                if hunk.new_no_newline {
+                    trailing_newline = false;
+                } else if hunk.old_no_newline {
+                    trailing_newline = true;
+                }
+                reports.push(HunkReport {
+                    hunk: index,
+                    applied: true,
+                    offset: Some(actual_offset),
+                    fuzz: Some(level),
+                });
+            }
+            None => {
+                failed = true;
+                reports.push(HunkReport {
+                    hunk: index,
+                    applied: false,
+                    offset: None,
+                    fuzz: None,
+                });
+            }
+        }
+    }
+
+    if failed {
+        return (None, reports);
+    }
+    lines.extend(source[cursor..].iter().cloned());
+    let text = Text {
+        lines,
+        trailing_newline,
+    };
+    (Some(text), reports)
+}
+
+/// Finds `pattern` in `lines` at or after `from`, as close to `near` as
+/// possible.
+fn find(lines: &[String], pattern: &[&str], from: usize, near: usize) -> Option<usize> {
+    let last = lines.len().checked_sub(pattern.len())?;
+    if from > last {
+        return None;
+    }
+    let near = near.clamp(from, last);
+    let matches_at = |at: usize| {
+        lines[at..at + pattern.len()]
+            .iter()
+            .zip(pattern)
+            .all(|(line, expected)| line == expected)
+    };
+    (0..=last - from).find_map(|distance| {
+        let after = near + distance;
+        if after <= last && matches_at(after) {
+            return Some(after);
+        }
+        let before = near.checked_sub(distance).filter(|&at| at >= from)?;
+        matches_at(before).then_some(before)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{access, apply_hunks, parse, Text};
+    use crate::permissions::Permission;
+
+    fn apply(content: &str, patch: &str, fuzz: usize) -> Option<String> {
+        let files = parse(patch, None).unwrap();
+        let (patched, _) = apply_hunks(&Text::parse(content), &files[0].hunks, fuzz);
+        patched.map(|text| text.render())
+    }
+
+    #[test]
+    fn parses_git_headers() {
+        let patch = "diff --git a/src/x.rs b/src/x.rs\nindex 1..2 100644\n--- a/src/x.rs\n+++ b/src/x.rs\n@@ -1 +1 @@\n-a\n+b\n--- /dev/null\n+++ b/new.txt\n@@ -0,0 +1,2 @@\n+one\n+two\n";
+        let files = parse(patch, None).unwrap();
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0].old_path.as_deref(), Some("src/x.rs"));
+        assert_eq!(files[0].new_path.as_deref(), Some("src/x.rs"));
+        assert_eq!(files[1].old_path, None);
+        assert_eq!(files[1].hunks[0].lines.len(), 2);
+        assert!(parse("--- a\n+++ b\n@@ -1,2 +1 @@\n-x\n", None).is_err());
+    }
+
+    #[test]
+    fn applies_hunks_at_an_offset() {
+        let patch = "--- a\n+++ a\n@@ -2,3 +2,3 @@\n b\n-c\n+C\n d\n";
+        assert_eq!(
+            apply("a\nb\nc\nd\ne\n", patch, 0).unwrap(),
+            "a\nb\nC\nd\ne\n"
+        );
+        // Two lines were added above the hunk since the diff was made
+        assert_eq!(
+            apply("x\ny\na\nb\nc\nd\ne\n", patch, 0).unwrap(),
+            "x\ny\na\nb\nC\nd\ne\n"
+        );
+    }
+
+    #[test]
+    fn fuzz_ignores_outer_context() {
+        let patch = "--- a\n+++ a\n@@ -1,3 +1,3 @@\n a\n-b\n+B\n c\n";
+        assert_eq!(apply("changed\nb\nc\n", patch, 0), None);
+        assert_eq!(
+            apply("changed\nb\nc\n", patch, 1).unwrap(),
+            "changed\nB\nc\n"
+        );
+        assert_eq!(apply("a\nx\nc\n", patch, 1), None);
+    }
+
+    #[test]
+    fn creates_files_and_tracks_missing_newlines() {
+        let patch =
+            "--- /dev/null\n+++ b/f\n@@ -0,0 +1,2 @@\n+one\n+two\n\\ No newline at end of file\n";
+        assert_eq!(apply("", patch, 0).unwrap(), "one\ntwo");
+
+        let patch = "--- a/f\n+++ b/f\n@@ -1 +1 @@\n-one\n\\ No newline at end of file\n+one\n";
+        assert_eq!(apply("one", patch, 0).unwrap(), "one\n");
+    }
+
+    #[test]
+    fn access_covers_each_file_the_patch_touches() {
+        let patch = "--- a/old.txt\n+++ b/new.txt\n@@ -1 +1 @@\n-a\n+b\n--- /dev/null\n+++ b/created.txt\n@@ -0,0 +1 @@\n+c\n";
+        assert_eq!(
+            access("src", patch, None),
+            vec![
+                (Permission::Read, "src/old.txt".to_string()),
+                (Permission::Write, "src/new.txt".to_string()),
+                (Permission::Delete, "src/old.txt".to_string()),
+                (Permission::Write, "src/created.txt".to_string()),
+            ]
+        );
+        assert!(access(".", "--- a\n", None).is_empty());
+    }
+
+    #[test]
+    fn fuzz_is_limited_by_the_context() {
+        let patch = "--- a\n+++ a\n@@ -1,3 +1,3 @@\n a\n-b\n+B\n c\n";
+        assert_eq!(apply("x\ny\nz\n", patch, usize::MAX), None);
+    }
+}
@@ -0,0 +1,107 @@
+//! Lexical normalization of request paths.
+//!
+//! Every path is interpreted relative to the filesystem handler's root.
+//! `.` segments and repeated slashes are dropped and `..` is resolved
+//! without touching the host, so the normalized path can be matched against
+//! permission rules and can never name anything above the root.
+
+/// Normalizes `path` to a `/`-separated path relative to the handler root,
+/// or `"."` for the root itself.
+pub fn normalize(path: &str) -> Result<String, String> {
+    if let Some(c) = path.chars().find(|c| c.is_control()) {
+        return Err(format!("Path contains control character {:?}", c));
+    }
+    if path.contains('\\') {
+        return Err("Path contains a backslash".to_string());
+    }
+    if path.starts_with('/') || has_drive_prefix(path) {
+        return Err(format!("Absolute paths are not allowed: '{}'", path));
+    }
+
+    let mut segments: Vec<&str> = Vec::new();
+    for segment in path.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                if segments.pop().is_none() {
+                    return Err(format!("Path escapes the root: '{}'", path));
+                }
+            }
+            segment => segments.push(segment),
+        }
+    }
+
+    if segments.is_empty() {
+        Ok(".".to_string())
+    } else {
+        Ok(segments.join("/"))
+    }
+}
+
+/// Joins a directory entry `name` onto a normalized directory path.
+pub fn join(dir: &str, name: &str) -> String {
+    if dir == "." {
+        name.to_string()
+    } else {
+        format!("{}/{}", dir, name)
+    }
+}
+
+fn has_drive_prefix(path: &str) -> bool {
+    let bytes = path.as_bytes();
+    bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::normalize;
+
+    #[test]
+    fn keeps_simple_paths() {
+        assert_eq!(normalize("a").unwrap(), "a");
+        assert_eq!(normalize("a/b/c.txt").unwrap(), "a/b/c.txt");
+    }
+
+    #[test]
+    fn root_forms_normalize_to_dot() {
+        assert_eq!(normalize("").unwrap(), ".");
+        assert_eq!(normalize(".").unwrap(), ".");
+        assert_eq!(normalize("./").unwrap(), ".");
+        assert_eq!(normalize("a/..").unwrap(), ".");
+    }
+
+    #[test]
+    fn collapses_dots_and_slashes() {
+        assert_eq!(normalize("./a//b/./c/").unwrap(), "a/b/c");
+        assert_eq!(normalize("a/b/../c").unwrap(), "a/c");
+        assert_eq!(normalize("a/./b/.././../c").unwrap(), "c");
+        assert_eq!(normalize("...").unwrap(), "...");
+        assert_eq!(normalize("a/..b").unwrap(), "a/..b");
+    }
+
+    #[test]
+    fn rejects_escapes() {
+        assert!(normalize("..").is_err());
+        assert!(normalize("../a").is_err());
+        assert!(normalize("a/../../b").is_err());
+        assert!(normalize("./a/b/../../..").is_err());
+        assert!(normalize("a/../b/../..").is_err());
+    }
+
+    #[test]
+    fn rejects_absolute_paths() {
+        assert!(normalize("/etc/passwd").is_err());
+        assert!(normalize("//server/share").is_err());
+        assert!(normalize("C:/Windows").is_err());
+        assert!(normalize("c:relative").is_err());
+    }
+
+    #[test]
+    fn rejects_control_characters_and_backslashes() {
+        assert!(normalize("a\0b").is_err());
+        assert!(normalize("a\nb").is_err());
+        assert!(normalize("a\u{7f}").is_err());
+        assert!(normalize("a\u{9b}b").is_err());
+        assert!(normalize("..\\..\\etc").is_err());
+    }
+}
@@ -0,0 +1,258 @@
+use crate::glob::{is_glob, Glob};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Permission {
+    Read,
+    Write,
+    Delete,
+}
+
+impl Permission {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "read" => Some(Permission::Read),
+            "write" => Some(Permission::Write),
+            "delete" => Some(Permission::Delete),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Permission {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Permission::Read => write!(f, "read"),
+            Permission::Write => write!(f, "write"),
+            Permission::Delete => write!(f, "delete"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Effect {
+    #[default]
+    Allow,
+    Deny,
+}
+
+/// A permission rule scoped to part of the tree under the handler root.
+///
+/// `path` is a directory/file prefix (`"scratch"` covers `scratch` and
+/// everything below it, `""` covers everything) or, if it contains glob
+/// metacharacters, a glob matched against the whole path.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PermissionRule {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub path: String,
+    pub operations: Vec<Permission>,
+    #[serde(default)]
+    pub effect: Effect,
+}
+
+impl PermissionRule {
+    /// A rule granting `operations` on every path, used for the legacy
+    /// global `permissions` list.
+    pub fn global(operations: Vec<Permission>) -> Self {
+        PermissionRule {
+            name: Some("global".to_string()),
+            path: String::new(),
+            operations,
+            effect: Effect::Allow,
+        }
+    }
+
+    /// Returns the rule's specificity for `path`, or `None` if it does not
+    /// apply. Longer literal patterns win; on a tie a plain prefix beats a
+    /// glob, and a deny beats an allow.
+    fn specificity(&self, path: &str) -> Option<(usize, bool, bool)> {
+        let pattern = trim_path(&self.path);
+        let (matched, literal_len, exact) = if is_glob(pattern) {
+            // Patterns are checked by `validate` when the rules are loaded
+            let glob = Glob::new(pattern).ok()?;
+            (glob.is_match(path), glob.literal_len(), false)
+        } else {
+            let matched = pattern.is_empty()
+                || path == pattern
+                || path
+                    .strip_prefix(pattern)
+                    .is_some_and(|rest| rest.starts_with('/'));
+            (matched, pattern.chars().count(), true)
+        };
+        matched.then_some((literal_len, exact, self.effect == Effect::Deny))
+    }
+
+    fn describe(&self, index: usize) -> String {
+        let name = match &self.name {
+            Some(name) => format!("'{}'", name),
+            None => format!("#{}", index),
+        };
+        let effect = match self.effect {
+            Effect::Allow => "allow",
+            Effect::Deny => "deny",
+        };
+        let operations: Vec<String> = self.operations.iter().map(|op| op.to_string()).collect();
+        format!(
+            "rule {} ({} {} on '{}')",
+            name,
+            effect,
+            operations.join(","),
+            self.path
+        )
+    }
+}
+
+/// Rejects rules whose glob patterns don't compile. Without this a
+/// mistyped deny rule would silently never match.
+pub fn validate(rules: &[PermissionRule]) -> Result<(), String> {
+    for (i, rule) in rules.iter().enumerate() {
+        let pattern = trim_path(&rule.path);
+        if is_glob(pattern) {
+            Glob::new(pattern).map_err(|e| format!("Invalid {}: {}", rule.describe(i), e))?;
+        }
+    }
+    Ok(())
+}
+
+/// Checks `permission` on `path` against `rules`, using the most specific
+/// rule that covers both. Anything not explicitly allowed is denied.
+pub fn check(rules: &[PermissionRule], permission: Permission, path: &str) -> Result<(), String> {
+    let path = trim_path(path);
+    let best = rules
+        .iter()
+        .enumerate()
+        .filter(|(_, rule)| rule.operations.contains(&permission))
+        .filter_map(|(i, rule)| rule.specificity(path).map(|s| (s, i, rule)))
+        .max_by_key(|(s, _, _)| *s);
+
+    match best {
+        Some((_, _, rule)) if rule.effect == Effect::Allow => Ok(()),
+        Some((_, i, rule)) => Err(format!(
+            "{} permission denied for '{}': blocked by {}",
+            capitalize(&permission.to_string()),
+            path,
+            rule.describe(i)
+        )),
+        None => Err(format!(
+            "{} permission denied for '{}': no rule grants {}",
+            capitalize(&permission.to_string()),
+            path,
+            permission
+        )),
+    }
+}
+
+fn trim_path(path: &str) -> &str {
+    let mut path = path.trim_end_matches('/');
+    loop {
+        if let Some(rest) = path.strip_prefix("./") {
+            path = rest;
+        } else if let Some(rest) = path.strip_prefix('/') {
+            path = rest;
+        } else {
+            break;
+        }
+    }
+    if path == "." {
+        ""
+    } else {
+        path
+    }
+}
+
+fn capitalize(s: &str) -> String {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check, validate, Effect, Permission, PermissionRule};
+
+    fn rule(path: &str, operations: &[Permission], effect: Effect) -> PermissionRule {
+        PermissionRule {
+            name: None,
+            path: path.to_string(),
+            operations: operations.to_vec(),
+            effect,
+        }
+    }
+
+    #[test]
+    fn nothing_is_allowed_without_a_matching_rule() {
+        let rules = [rule("scratch", &[Permission::Write], Effect::Allow)];
+        let error = check(&rules, Permission::Write, "src/lib.rs").unwrap_err();
+        assert!(error.contains("no rule grants write"));
+        assert!(check(&rules, Permission::Read, "scratch/a").is_err());
+        assert!(check(&[], Permission::Read, "a").is_err());
+    }
+
+    #[test]
+    fn global_rule_covers_every_path() {
+        let rules = [PermissionRule::global(vec![Permission::Read])];
+        assert!(check(&rules, Permission::Read, ".").is_ok());
+        assert!(check(&rules, Permission::Read, "a/b/c").is_ok());
+        assert!(check(&rules, Permission::Write, "a").is_err());
+    }
+
+    #[test]
+    fn prefixes_match_whole_components() {
+        let rules = [rule("scratch", &[Permission::Write], Effect::Allow)];
+        assert!(check(&rules, Permission::Write, "scratch").is_ok());
+        assert!(check(&rules, Permission::Write, "./scratch/a/b").is_ok());
+        assert!(check(&rules, Permission::Write, "scratchpad").is_err());
+    }
+
+    #[test]
+    fn most_specific_rule_wins() {
+        let rules = [
+            PermissionRule::global(vec![Permission::Read]),
+            rule("secret", &[Permission::Read], Effect::Deny),
+            rule("secret/public", &[Permission::Read], Effect::Allow),
+        ];
+        assert!(check(&rules, Permission::Read, "src").is_ok());
+        let error = check(&rules, Permission::Read, "secret/key").unwrap_err();
+        assert!(error.contains("deny read on 'secret'"));
+        assert!(check(&rules, Permission::Read, "secret/public/a").is_ok());
+    }
+
+    #[test]
+    fn prefix_beats_glob_of_equal_length_and_deny_breaks_ties() {
+        // Both have the literal "a/b"; the plain prefix is more specific
+        let rules = [
+            rule("a/b*", &[Permission::Write], Effect::Deny),
+            rule("a/b", &[Permission::Write], Effect::Allow),
+        ];
+        assert!(check(&rules, Permission::Write, "a/b").is_ok());
+        assert!(check(&rules, Permission::Write, "a/bc").is_err());
+
+        let rules = [
+            rule("src", &[Permission::Write], Effect::Allow),
+            rule("src", &[Permission::Write], Effect::Deny),
+        ];
+        assert!(check(&rules, Permission::Write, "src/a").is_err());
+    }
+
+    #[test]
+    fn globs_match_the_whole_path() {
+        let rules = [
+            PermissionRule::global(vec![Permission::Read]),
+            rule("**/*.key", &[Permission::Read], Effect::Deny),
+        ];
+        assert!(check(&rules, Permission::Read, "a/b/c.key").is_err());
+        assert!(check(&rules, Permission::Read, "a/b/c.keys").is_ok());
+    }
+
+    #[test]
+    fn malformed_globs_are_rejected() {
+        assert!(validate(&[rule("secret[", &[Permission::Read], Effect::Deny)]).is_err());
+        assert!(validate(&[rule("src/**", &[Permission::Read], Effect::Allow)]).is_ok());
+    }
+}
@@ -0,0 +1,289 @@
+//! `read-file`, including partial reads and the line-numbered view.
+
+use crate::bindings::ntwk::theater::filesystem;
+use crate::hash::sha1_hex;
+use crate::logging::log;
+use crate::request::{Encoding, ReadOptions};
+use crate::response::{ErrorCode, FsError, FsResult};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde_json::json;
+use std::borrow::Cow;
+use std::fmt::Write;
+use std::str::Utf8Error;
+
+const DEFAULT_LINE_NUMBER_WIDTH: usize = 6;
+/// Wider than any line number; `format!` panics on widths over `u16::MAX`.
+const MAX_LINE_NUMBER_WIDTH: usize = 20;
+const DEFAULT_MAX_LINE_LENGTH: usize = 2000;
+const DEFAULT_MAX_LINES: usize = 2000;
+const TRUNCATION_MARKER: &str = "... [truncated]";
+
+pub fn read_file(path: &str, options: &ReadOptions) -> FsResult {
+    log(&format!("Reading file: {}", path));
+    let content =
+        filesystem::read_file(path).map_err(|e| FsError::host("Failed to read file", e))?;
+    log(&format!("Read file: {}", path));
+    let hash = sha1_hex(&content);
+
+    let by_bytes = options.offset.is_some() || options.length.is_some();
+    let by_lines = options.start_line.is_some() || options.end_line.is_some();
+    if by_bytes && by_lines {
+        return Err(FsError::new(
+            ErrorCode::InvalidRequest,
+            "Byte ranges (offset/length) and line ranges (start_line/end_line) cannot be combined",
+        ));
+    }
+    if options.encoding == Encoding::Base64 {
+        if by_lines || options.view {
+            return Err(FsError::new(
+                ErrorCode::InvalidRequest,
+                "base64 reads support byte ranges (offset/length) only",
+            ));
+        }
+        let bytes = byte_range(&content, options.offset, options.length);
+        return Ok(Some(json!({
+            "content": BASE64.encode(bytes),
+            "hash": hash,
+            "total_size": content.len(),
+        })));
+    }
+
+    let text = decode(&content, options.lossy).map_err(|_| {
+        FsError::new(
+            ErrorCode::UnsupportedOperation,
+            format!(
+                "'{}' is not valid UTF-8; read it with base64 encoding or set lossy",
+                path
+            ),
+        )
+    })?;
+    if options.view {
+        if by_bytes {
+            return Err(FsError::new(
+                ErrorCode::InvalidRequest,
+                "view cannot be combined with byte ranges (offset/length)",
+            ));
+        }
+        let mut result = view(&text, options)?;
+        result["hash"] = json!(hash);
+        return Ok(Some(result));
+    }
+    if !by_bytes && !by_lines {
+        return Ok(Some(json!({
+            "content": text,
+            "hash": hash,
+            "total_size": content.len(),
+        })));
+    }
+
+    let total_lines = text.lines().count();
+    let slice = if by_bytes {
+        let bytes = byte_range(&content, options.offset, options.length);
+        decode(bytes, options.lossy)
+            .map_err(|_| {
+                FsError::new(
+                    ErrorCode::InvalidRequest,
+                    "Byte range splits a UTF-8 character; use base64 encoding or set lossy",
+                )
+            })?
+            .to_string()
+    } else {
+        line_range(&text, options.start_line, options.end_line)?.to_string()
+    };
+    Ok(Some(json!({
+        "content": slice,
+        "hash": hash,
+        "total_size": content.len(),
+        "total_lines": total_lines,
+    })))
+}
+
+/// Decodes UTF-8, replacing invalid sequences only when `lossy` is set.
+fn decode(bytes: &[u8], lossy: bool) -> Result<Cow<'_, str>, Utf8Error> {
+    if lossy {
+        Ok(String::from_utf8_lossy(bytes))
+    } else {
+        std::str::from_utf8(bytes).map(Cow::Borrowed)
+    }
+}
+
+/// The bytes from `offset` (default 0) for `length` bytes (default to the
+/// end), clamped to the content.
+fn byte_range(content: &[u8], offset: Option<u64>, length: Option<u64>) -> &[u8] {
+    // u64 doesn't fit in usize on wasm32; anything larger is past the end
+    let to_usize = |n: u64| usize::try_from(n).unwrap_or(usize::MAX);
+    let start = to_usize(offset.unwrap_or(0)).min(content.len());
+    let end = match length {
+        Some(length) => start.saturating_add(to_usize(length)).min(content.len()),
+        None => content.len(),
+    };
+    &content[start..end]
+}
+
+/// Lines `start` through `end` (1-based, inclusive, defaulting to the first
+/// and last line), including their line endings.
+fn line_range(text: &str, start: Option<usize>, end: Option<usize>) -> Result<&str, FsError> {
+    let start = start.unwrap_or(1);
+    if start == 0 || end == Some(0) {
+        return Err(FsError::new(
+            ErrorCode::InvalidRequest,
+            "Line numbers start at 1",
+        ));
+    }
+    if end.is_some_and(|end| end < start) {
+        return Err(FsError::new(
+            ErrorCode::InvalidRequest,
+            "end_line must not be before start_line",
+        ));
+    }
+
+    let mut begin = text.len();
+    let mut finish = text.len();
+    let mut offset = 0;
+    for (i, line) in text.split_inclusive('\n').enumerate() {
+        let number = i + 1;
+        if number == start {
+            begin = offset;
+        }
+        offset += line.len();
+        if Some(number) == end {
+            finish = offset;
+            break;
+        }
+    }
+    Ok(&text[begin.min(finish)..finish])
+}
+
+/// Renders the selected lines prefixed with their line numbers, cutting
+/// overly long lines and stopping after `max_lines`.
+fn view(text: &str, options: &ReadOptions) -> Result<serde_json::Value, FsError> {
+    let selected = line_range(text, options.start_line, options.end_line)?;
+    let first = options.start_line.unwrap_or(1);
+    let width = options
+        .line_number_width
+        .unwrap_or(DEFAULT_LINE_NUMBER_WIDTH)
+        .min(MAX_LINE_NUMBER_WIDTH);
+    let max_line_length = options.max_line_length.unwrap_or(DEFAULT_MAX_LINE_LENGTH);
+    let max_lines = options.max_lines.unwrap_or(DEFAULT_MAX_LINES);
+
+    let mut output = String::new();
+    let mut shown = 0;
+    let mut truncated = false;
+    for line in selected.lines() {
+        if shown == max_lines {
+            truncated = true;
+            break;
+        }
+        let number = first + shown;
+        match line.char_indices().nth(max_line_length) {
+            Some((cut, _)) => {
+                truncated = true;
+                let _ = writeln!(
+                    output,
+                    "{:>width$}\t{}{}",
+                    number,
+                    &line[..cut],
+                    TRUNCATION_MARKER
+                );
+            }
+            None => {
+                let _ = writeln!(output, "{:>width$}\t{}", number, line);
+            }
+        }
+        shown += 1;
+    }
+
+    Ok(json!({
+        "content": output,
+        "start_line": first,
+        "end_line": if shown == 0 { None } else { Some(first + shown - 1) },
+        "total_lines": text.lines().count(),
+        "truncated": truncated,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{byte_range, decode, line_range, view};
+    use crate::request::ReadOptions;
+
+    #[test]
+    fn byte_ranges_are_clamped() {
+        let content = b"hello world";
+        assert_eq!(byte_range(content, Some(6), None), b"world");
+        assert_eq!(byte_range(content, None, Some(5)), b"hello");
+        assert_eq!(byte_range(content, Some(6), Some(100)), b"world");
+        assert_eq!(byte_range(content, Some(100), Some(5)), b"");
+        assert_eq!(byte_range(content, Some(1 << 32), None), b"");
+        assert_eq!(byte_range(content, Some(6), Some(u64::MAX)), b"world");
+    }
+
+    #[test]
+    fn invalid_utf8_is_an_error_unless_lossy() {
+        assert!(decode(b"ok\xff", false).is_err());
+        assert_eq!(decode(b"ok\xff", true).unwrap(), "ok\u{fffd}");
+        assert_eq!(decode("é".as_bytes(), false).unwrap(), "é");
+    }
+
+    #[test]
+    fn line_ranges_keep_line_endings() {
+        let text = "one\ntwo\r\nthree\nfour";
+        assert_eq!(
+            line_range(text, Some(2), Some(3)).unwrap(),
+            "two\r\nthree\n"
+        );
+        assert_eq!(line_range(text, Some(3), None).unwrap(), "three\nfour");
+        assert_eq!(line_range(text, None, Some(1)).unwrap(), "one\n");
+        assert_eq!(line_range(text, Some(4), Some(10)).unwrap(), "four");
+        assert_eq!(line_range(text, Some(5), None).unwrap(), "");
+    }
+
+    #[test]
+    fn invalid_line_ranges_are_rejected() {
+        assert!(line_range("a\nb", Some(0), None).is_err());
+        assert!(line_range("a\nb", Some(2), Some(1)).is_err());
+    }
+
+    #[test]
+    fn view_numbers_lines_from_the_start_line() {
+        let options = ReadOptions {
+            view: true,
+            start_line: Some(2),
+            end_line: Some(3),
+            line_number_width: Some(3),
+            ..Default::default()
+        };
+        let result = view("a\nb\nc\nd\n", &options).unwrap();
+        assert_eq!(result["content"], "  2\tb\n  3\tc\n");
+        assert_eq!(result["end_line"], 3);
+        assert_eq!(result["total_lines"], 4);
+        assert_eq!(result["truncated"], false);
+    }
+
+    #[test]
+    fn view_truncates_long_lines_and_line_count() {
+        let options = ReadOptions {
+            view: true,
+            line_number_width: Some(1),
+            max_line_length: Some(3),
+            max_lines: Some(2),
+            ..Default::default()
+        };
+        let result = view("abcdef\nxy\nz\n", &options).unwrap();
+        assert_eq!(result["content"], "1\tabc... [truncated]\n2\txy\n");
+        assert_eq!(result["end_line"], 2);
+        assert_eq!(result["truncated"], true);
+    }
+
+    #[test]
+    fn view_clamps_the_line_number_width() {
+        let options = ReadOptions {
+            view: true,
+            line_number_width: Some(70000),
+            ..Default::default()
+        };
+        let result = view("a\n", &options).unwrap();
+        assert_eq!(result["content"], format!("{:>20}\ta\n", 1));
+    }
+}
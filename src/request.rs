@@ -0,0 +1,525 @@
+use crate::blobs::{self, BLOB_DIR};
+use crate::edit::{Edit, LinePosition};
+use crate::kv;
+use crate::patch;
+use crate::permissions::Permission;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::borrow::Cow;
+
+/// A filesystem request, tagged by its `operation` field.
+///
+/// Each variant carries exactly the fields its operation needs, so a
+/// request missing one of them is rejected when it is deserialized. Fields
+/// that belong to other operations (e.g. `"content": null` on a
+/// `read-file`) are ignored, which keeps the older flat request shape
+/// working.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "operation", rename_all = "kebab-case")]
+pub enum FsRequest {
+    ReadFile {
+        path: String,
+        #[serde(flatten)]
+        options: ReadOptions,
+    },
+    ListFiles {
+        path: String,
+    },
+    Exists {
+        path: String,
+    },
+    /// Recursive listing of `path`. Globs are matched against paths
+    /// relative to `path`; `exclude` also prunes directories.
+    Tree {
+        path: String,
+        #[serde(default)]
+        max_depth: Option<usize>,
+        #[serde(default)]
+        include: Vec<String>,
+        #[serde(default)]
+        exclude: Vec<String>,
+    },
+    /// Paths below `path` whose path relative to it matches `pattern`.
+    Glob {
+        #[serde(default = "root")]
+        path: String,
+        pattern: String,
+        #[serde(default)]
+        max_results: Option<usize>,
+    },
+    /// Lines matching `pattern` in the text files below `path`.
+    Search {
+        #[serde(default = "root")]
+        path: String,
+        pattern: String,
+        /// Treat `pattern` as a regular expression rather than a literal.
+        #[serde(default)]
+        regex: bool,
+        #[serde(default = "enabled")]
+        case_sensitive: bool,
+        #[serde(default)]
+        include: Vec<String>,
+        #[serde(default)]
+        exclude: Vec<String>,
+        /// Lines of context returned before and after each match.
+        #[serde(default)]
+        context: usize,
+        #[serde(default)]
+        max_matches: Option<usize>,
+    },
+    /// SHA-1 (and optionally SHA-256) of a file, or with `recursive` a
+    /// manifest of every file below a directory.
+    Hash {
+        path: String,
+        #[serde(default)]
+        sha256: bool,
+        #[serde(default)]
+        recursive: bool,
+    },
+    WriteFile {
+        path: String,
+        content: String,
+        #[serde(default)]
+        encoding: Encoding,
+        /// Only write if the file's current hash matches.
+        #[serde(default)]
+        expected_hash: Option<String>,
+    },
+    Append {
+        path: String,
+        content: String,
+        /// Start the content on its own line and end it with a newline.
+        #[serde(default)]
+        ensure_newline: bool,
+    },
+    CreateDir {
+        path: String,
+    },
+    DeleteFile {
+        path: String,
+        #[serde(default)]
+        expected_hash: Option<String>,
+    },
+    DeleteDir {
+        path: String,
+    },
+    EditFile {
+        path: String,
+        #[serde(flatten)]
+        edit: Edit,
+        #[serde(default)]
+        expected_hash: Option<String>,
+    },
+    /// Several edits to one file, written only if all of them apply.
+    MultiEdit {
+        path: String,
+        edits: Vec<Edit>,
+    },
+    /// Inserts `text` as whole lines before or after `line` (1-based).
+    InsertLines {
+        path: String,
+        line: usize,
+        #[serde(default)]
+        position: LinePosition,
+        text: String,
+    },
+    /// Deletes lines `start_line` through `end_line`, inclusive.
+    DeleteLines {
+        path: String,
+        start_line: usize,
+        end_line: usize,
+    },
+    /// Applies a unified diff to the files it names, relative to `path`.
+    /// Nothing is written unless every hunk applies.
+    Patch {
+        #[serde(default = "root")]
+        path: String,
+        patch: String,
+        /// Context lines that may be ignored at each end of a hunk.
+        #[serde(default)]
+        fuzz: usize,
+        /// Leading path components to strip from the file names; by default
+        /// git's `a/` and `b/` prefixes are removed.
+        #[serde(default)]
+        strip: Option<usize>,
+    },
+    /// A unified diff of `path` against `other`, or against `content`.
+    Diff {
+        path: String,
+        #[serde(default)]
+        other: Option<String>,
+        #[serde(default)]
+        content: Option<String>,
+        /// Lines of unchanged context around each change (default 3).
+        #[serde(default)]
+        context: Option<usize>,
+    },
+    Move {
+        source: String,
+        destination: String,
+        #[serde(default)]
+        overwrite: bool,
+    },
+    Copy {
+        source: String,
+        destination: String,
+        #[serde(default)]
+        overwrite: bool,
+        #[serde(default)]
+        skip_existing: bool,
+        /// Upper bound on the total size of the files copied.
+        #[serde(default)]
+        max_bytes: Option<u64>,
+    },
+    /// Stores content under `blobs/`, addressed by its SHA-1.
+    StoreContent {
+        content: String,
+        #[serde(default)]
+        encoding: Encoding,
+    },
+    /// Fetches a stored blob by hash, verifying its content.
+    GetContent {
+        hash: String,
+        #[serde(default)]
+        encoding: Encoding,
+    },
+    /// Stores a JSON value under `key` in the key-value store.
+    Put {
+        key: String,
+        value: Value,
+    },
+    Get {
+        key: String,
+    },
+    Delete {
+        key: String,
+    },
+    /// The stored keys starting with `prefix`.
+    ListKeys {
+        #[serde(default)]
+        prefix: String,
+    },
+    /// Runs `requests` in order. With `atomic`, every item is validated
+    /// up front and a failure rolls back the items already applied.
+    Batch {
+        requests: Vec<FsRequest>,
+        #[serde(default)]
+        atomic: bool,
+    },
+}
+
+/// The `operation` names of every `FsRequest` variant, so an unknown
+/// operation can be told apart from a malformed request.
+pub const OPERATIONS: &[&str] = &[
+    "read-file",
+    "list-files",
+    "exists",
+    "tree",
+    "glob",
+    "search",
+    "hash",
+    "write-file",
+    "append",
+    "create-dir",
+    "delete-file",
+    "delete-dir",
+    "edit-file",
+    "multi-edit",
+    "insert-lines",
+    "delete-lines",
+    "patch",
+    "diff",
+    "move",
+    "copy",
+    "store-content",
+    "get-content",
+    "put",
+    "get",
+    "delete",
+    "list-keys",
+    "batch",
+];
+
+/// Optional ways to read part of a file. Every read also returns the SHA-1
+/// `hash` of the whole file, for use as an `expected_hash`, so even a plain
+/// read returns an object with `content`, `hash` and `total_size`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ReadOptions {
+    /// Byte offset to start reading from.
+    #[serde(default)]
+    pub offset: Option<u64>,
+    /// Maximum number of bytes to read.
+    #[serde(default)]
+    pub length: Option<u64>,
+    /// First line to read, counting from 1.
+    #[serde(default)]
+    pub start_line: Option<usize>,
+    /// Last line to read, inclusive.
+    #[serde(default)]
+    pub end_line: Option<usize>,
+    /// Return the lines prefixed with their line numbers.
+    #[serde(default)]
+    pub view: bool,
+    #[serde(default)]
+    pub line_number_width: Option<usize>,
+    /// In `view`, longer lines are cut off with a marker.
+    #[serde(default)]
+    pub max_line_length: Option<usize>,
+    /// In `view`, the most lines returned.
+    #[serde(default)]
+    pub max_lines: Option<usize>,
+    #[serde(default)]
+    pub encoding: Encoding,
+    /// Replace invalid UTF-8 instead of failing the read.
+    #[serde(default)]
+    pub lossy: bool,
+}
+
+/// How file content is carried in a request or response. `base64` carries
+/// the exact bytes.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Encoding {
+    #[default]
+    Utf8,
+    Base64,
+}
+
+impl FsRequest {
+    /// The paths the request touches, each with the permission it needs.
+    /// Key-value requests touch the file their key is stored in.
+    pub fn access(&self) -> Vec<(Permission, Cow<'_, str>)> {
+        let paths: Vec<(Permission, &str)> = match self {
+            FsRequest::ReadFile { path, .. }
+            | FsRequest::ListFiles { path }
+            | FsRequest::Exists { path }
+            | FsRequest::Tree { path, .. }
+            | FsRequest::Glob { path, .. }
+            | FsRequest::Search { path, .. }
+            | FsRequest::Hash { path, .. } => vec![(Permission::Read, path)],
+            FsRequest::WriteFile { path, .. }
+            | FsRequest::Append { path, .. }
+            | FsRequest::CreateDir { path }
+            | FsRequest::EditFile { path, .. }
+            | FsRequest::MultiEdit { path, .. }
+            | FsRequest::InsertLines { path, .. }
+            | FsRequest::DeleteLines { path, .. } => vec![(Permission::Write, path)],
+            FsRequest::DeleteFile { path, .. } | FsRequest::DeleteDir { path } => {
+                vec![(Permission::Delete, path)]
+            }
+            FsRequest::Diff { path, other, .. } => std::iter::once(path)
+                .chain(other)
+                .map(|path| (Permission::Read, path.as_str()))
+                .collect(),
+            FsRequest::Move {
+                source,
+                destination,
+                ..
+            } => vec![
+                (Permission::Read, source),
+                (Permission::Delete, source),
+                (Permission::Write, destination),
+            ],
+            FsRequest::Copy {
+                source,
+                destination,
+                ..
+            } => vec![(Permission::Read, source), (Permission::Write, destination)],
+            FsRequest::StoreContent { .. } => vec![(Permission::Write, BLOB_DIR)],
+            FsRequest::GetContent { hash, .. } => {
+                return vec![(Permission::Read, Cow::Owned(blobs::read_path(hash)))]
+            }
+            FsRequest::Put { key, .. } => {
+                return vec![(Permission::Write, Cow::Owned(kv::key_path(key)))]
+            }
+            FsRequest::Get { key } => {
+                return vec![(Permission::Read, Cow::Owned(kv::key_path(key)))]
+            }
+            FsRequest::Delete { key } => {
+                return vec![(Permission::Delete, Cow::Owned(kv::key_path(key)))]
+            }
+            FsRequest::ListKeys { .. } => vec![(Permission::Read, kv::DATA_DIR)],
+            // The files a patch names, not the directory it is applied in
+            FsRequest::Patch {
+                path, patch, strip, ..
+            } => {
+                return patch::access(path, patch, *strip)
+                    .into_iter()
+                    .map(|(permission, path)| (permission, Cow::Owned(path)))
+                    .collect()
+            }
+            // Batch items are checked individually when the batch runs
+            FsRequest::Batch { .. } => Vec::new(),
+        };
+        paths
+            .into_iter()
+            .map(|(permission, path)| (permission, Cow::Borrowed(path)))
+            .collect()
+    }
+
+    /// Mutable access to every path in the request, for normalization.
+    pub fn paths_mut(&mut self) -> Vec<&mut String> {
+        match self {
+            FsRequest::ReadFile { path, .. }
+            | FsRequest::ListFiles { path }
+            | FsRequest::Exists { path }
+            | FsRequest::Tree { path, .. }
+            | FsRequest::Glob { path, .. }
+            | FsRequest::Search { path, .. }
+            | FsRequest::Hash { path, .. }
+            | FsRequest::WriteFile { path, .. }
+            | FsRequest::Append { path, .. }
+            | FsRequest::CreateDir { path }
+            | FsRequest::DeleteFile { path, .. }
+            | FsRequest::DeleteDir { path }
+            | FsRequest::EditFile { path, .. }
+            | FsRequest::MultiEdit { path, .. }
+            | FsRequest::InsertLines { path, .. }
+            | FsRequest::DeleteLines { path, .. }
+            | FsRequest::Patch { path, .. } => vec![path],
+            FsRequest::Move {
+                source,
+                destination,
+                ..
+            }
+            | FsRequest::Copy {
+                source,
+                destination,
+                ..
+            } => vec![source, destination],
+            FsRequest::Diff { path, other, .. } => std::iter::once(path).chain(other).collect(),
+            FsRequest::StoreContent { .. }
+            | FsRequest::GetContent { .. }
+            | FsRequest::Put { .. }
+            | FsRequest::Get { .. }
+            | FsRequest::Delete { .. }
+            | FsRequest::ListKeys { .. }
+            | FsRequest::Batch { .. } => Vec::new(),
+        }
+    }
+}
+
+fn root() -> String {
+    ".".to_string()
+}
+
+fn enabled() -> bool {
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FsRequest, OPERATIONS};
+    use serde_json::json;
+
+    fn parse(value: serde_json::Value) -> Result<FsRequest, serde_json::Error> {
+        serde_json::from_value(value)
+    }
+
+    #[test]
+    fn legacy_flat_requests_still_parse() {
+        let request = parse(json!({
+            "operation": "read-file",
+            "path": "a.txt",
+            "content": null,
+            "old_text": null,
+            "new_text": null,
+        }))
+        .unwrap();
+        match request {
+            FsRequest::ReadFile { path, options } => {
+                assert_eq!(path, "a.txt");
+                assert_eq!(options.offset, None);
+                assert!(!options.view);
+            }
+            other => panic!("unexpected request: {:?}", other),
+        }
+
+        let request = parse(json!({
+            "operation": "write-file",
+            "path": "a.txt",
+            "content": "hello",
+            "old_text": null,
+            "new_text": null,
+        }))
+        .unwrap();
+        assert!(matches!(request, FsRequest::WriteFile { content, .. } if content == "hello"));
+    }
+
+    #[test]
+    fn flattened_edit_fields_parse() {
+        let request = parse(json!({
+            "operation": "edit-file",
+            "path": "a.txt",
+            "content": null,
+            "old_text": "x",
+            "new_text": "y",
+        }))
+        .unwrap();
+        match request {
+            FsRequest::EditFile { edit, .. } => {
+                assert_eq!((edit.old_text.as_str(), edit.new_text.as_str()), ("x", "y"));
+                assert!(!edit.replace_all);
+                assert_eq!(edit.occurrence, None);
+            }
+            other => panic!("unexpected request: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn flattened_read_options_parse() {
+        let request = parse(json!({
+            "operation": "read-file",
+            "path": "a.txt",
+            "start_line": 2,
+            "view": true,
+        }))
+        .unwrap();
+        match request {
+            FsRequest::ReadFile { options, .. } => {
+                assert_eq!(options.start_line, Some(2));
+                assert!(options.view);
+            }
+            other => panic!("unexpected request: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn missing_fields_are_rejected() {
+        assert!(parse(json!({ "operation": "write-file", "path": "a.txt" })).is_err());
+        assert!(parse(json!({ "operation": "write-file", "content": "x" })).is_err());
+        assert!(parse(json!({
+            "operation": "edit-file",
+            "path": "a.txt",
+            "old_text": "x",
+            "new_text": null,
+        }))
+        .is_err());
+        assert!(parse(json!({ "operation": "read-file" })).is_err());
+    }
+
+    #[test]
+    fn every_listed_operation_is_a_variant() {
+        for operation in OPERATIONS {
+            let error = parse(json!({ "operation": operation })).err();
+            assert!(
+                !error.is_some_and(|e| e.to_string().starts_with("unknown variant")),
+                "{} is not a variant",
+                operation
+            );
+        }
+    }
+
+    #[test]
+    fn every_variant_is_listed() {
+        // serde's error for an unknown tag names every variant
+        let error = parse(json!({ "operation": "no-such-operation" }))
+            .unwrap_err()
+            .to_string();
+        let variants: Vec<&str> = error.split('`').skip(3).step_by(2).collect();
+        assert_eq!(variants.len(), OPERATIONS.len(), "{}", error);
+        for variant in variants {
+            assert!(OPERATIONS.contains(&variant), "{} is not listed", variant);
+        }
+    }
+}
@@ -0,0 +1,176 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Machine-readable classification of a failed request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    PermissionDenied,
+    NotFound,
+    InvalidRequest,
+    InvalidPath,
+    UnsupportedOperation,
+    Conflict,
+    Ambiguous,
+    LimitExceeded,
+    HostError,
+}
+
+impl ErrorCode {
+    /// Best-effort classification of an error string returned by the host
+    /// `filesystem` imports, which only report failures as text.
+    pub fn from_host_error(error: &str) -> Self {
+        let error = error.to_lowercase();
+        if error.contains("no such file")
+            || error.contains("not found")
+            || error.contains("does not exist")
+        {
+            ErrorCode::NotFound
+        } else if error.contains("permission denied")
+            || error.contains("access denied")
+            || error.contains("not allowed")
+        {
+            ErrorCode::PermissionDenied
+        } else if error.contains("exists") || error.contains("not empty") {
+            ErrorCode::Conflict
+        } else if error.contains("is a directory") || error.contains("not a directory") {
+            ErrorCode::InvalidRequest
+        } else {
+            ErrorCode::HostError
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FsError {
+    pub code: ErrorCode,
+    pub message: String,
+    /// Extra detail returned alongside the error, e.g. partial results.
+    pub data: Option<serde_json::Value>,
+}
+
+impl FsError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        FsError {
+            code,
+            message: message.into(),
+            data: None,
+        }
+    }
+
+    /// Wraps an error from a host import, e.g.
+    /// `FsError::host("Failed to read file", e)`.
+    pub fn host(context: &str, error: String) -> Self {
+        FsError {
+            code: ErrorCode::from_host_error(&error),
+            message: format!("{}: {}", context, error),
+            data: None,
+        }
+    }
+
+    /// The error for a file that has to be handled as text but isn't
+    /// valid UTF-8.
+    pub fn not_text(path: &str) -> Self {
+        FsError::new(
+            ErrorCode::UnsupportedOperation,
+            format!(
+                "'{}' is not valid UTF-8; only text files are supported",
+                path
+            ),
+        )
+    }
+
+    /// Prefixes the message with where the error happened, keeping its code.
+    pub fn context(self, context: &str) -> Self {
+        FsError {
+            message: format!("{}: {}", context, self.message),
+            ..self
+        }
+    }
+
+    pub fn with_data(self, data: serde_json::Value) -> Self {
+        FsError {
+            data: Some(data),
+            ..self
+        }
+    }
+}
+
+impl fmt::Display for FsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+pub type FsResult = Result<Option<serde_json::Value>, FsError>;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FsResponse {
+    pub success: bool,
+    pub data: Option<serde_json::Value>,
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<ErrorCode>,
+    /// The request's id, echoed back so callers can correlate responses.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<serde_json::Value>,
+}
+
+impl From<FsResult> for FsResponse {
+    fn from(result: FsResult) -> Self {
+        match result {
+            Ok(data) => FsResponse {
+                success: true,
+                data,
+                error: None,
+                code: None,
+                id: None,
+            },
+            Err(e) => FsResponse {
+                success: false,
+                data: e.data,
+                error: Some(e.message),
+                code: Some(e.code),
+                id: None,
+            },
+        }
+    }
+}
+
+impl From<FsError> for FsResponse {
+    fn from(error: FsError) -> Self {
+        FsResponse::from(Err(error))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ErrorCode;
+
+    #[test]
+    fn host_errors_are_classified() {
+        let cases = [
+            (
+                "No such file or directory (os error 2)",
+                ErrorCode::NotFound,
+            ),
+            ("Path not found", ErrorCode::NotFound),
+            // Must not be taken for "exists"
+            ("File does not exist", ErrorCode::NotFound),
+            (
+                "Permission denied (os error 13)",
+                ErrorCode::PermissionDenied,
+            ),
+            ("Access denied", ErrorCode::PermissionDenied),
+            ("Operation not allowed", ErrorCode::PermissionDenied),
+            ("File exists (os error 17)", ErrorCode::Conflict),
+            ("Directory not empty", ErrorCode::Conflict),
+            ("Is a directory (os error 21)", ErrorCode::InvalidRequest),
+            ("Not a directory", ErrorCode::InvalidRequest),
+            ("Disk quota exceeded", ErrorCode::HostError),
+        ];
+        for (error, code) in cases {
+            assert_eq!(ErrorCode::from_host_error(error), code, "{}", error);
+        }
+    }
+}
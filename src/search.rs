@@ -0,0 +1,269 @@
+//! Finding files by name and by content.
+
+use crate::bindings::ntwk::theater::filesystem;
+use crate::glob::{is_glob, Glob};
+use crate::logging::log;
+use crate::path::{self, join};
+use crate::permissions::{self, Permission, PermissionRule};
+use crate::response::{ErrorCode, FsError, FsResult};
+use crate::walk::{compile_globs, walk, Visit};
+use regex::{Regex, RegexBuilder};
+use serde::Serialize;
+use serde_json::json;
+
+const DEFAULT_MAX_RESULTS: usize = 1000;
+const DEFAULT_MAX_MATCHES: usize = 500;
+/// How much of a file is checked for NUL bytes to decide it is binary.
+const BINARY_SNIFF_LEN: usize = 8192;
+
+pub struct SearchOptions {
+    pub regex: bool,
+    pub case_sensitive: bool,
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    pub context: usize,
+    pub max_matches: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+struct Match {
+    path: String,
+    line_number: usize,
+    line: String,
+    before: Vec<String>,
+    after: Vec<String>,
+}
+
+/// Finds paths below `root` whose path relative to `root` matches
+/// `pattern`.
+pub fn glob(
+    root: &str,
+    pattern: &str,
+    max_results: Option<usize>,
+    rules: &[PermissionRule],
+) -> FsResult {
+    log(&format!("Globbing {} in: {}", pattern, root));
+    let max_results = max_results.unwrap_or(DEFAULT_MAX_RESULTS);
+
+    // Start the walk below any leading literal segments of the pattern, and
+    // don't walk deeper than the pattern can match.
+    let segments: Vec<&str> = pattern
+        .split('/')
+        .filter(|s| !s.is_empty() && *s != ".")
+        .collect();
+    if segments.contains(&"..") {
+        return Err(FsError::new(
+            ErrorCode::InvalidRequest,
+            "Glob patterns cannot contain '..'",
+        ));
+    }
+    let literal = segments[..segments.len().saturating_sub(1)]
+        .iter()
+        .take_while(|segment| !is_glob(segment))
+        .count();
+    let mut base = root.to_string();
+    for segment in &segments[..literal] {
+        base = join(&base, segment);
+    }
+    let base = path::normalize(&base).map_err(|e| FsError::new(ErrorCode::InvalidPath, e))?;
+    let rest = segments[literal..].join("/");
+    let max_depth = if rest.contains("**") || rest.contains('{') {
+        None
+    } else {
+        Some(segments.len() - literal)
+    };
+    let glob = Glob::new(&rest).map_err(|e| FsError::new(ErrorCode::InvalidRequest, e))?;
+
+    let mut matches = Vec::new();
+    let mut truncated = false;
+    let searchable = literal == 0
+        || (permissions::check(rules, Permission::Read, &base).is_ok()
+            && filesystem::list_files(&base).is_ok());
+    if searchable {
+        walk(&base, rules, max_depth, |entry| {
+            if glob.is_match(entry.relative) {
+                if matches.len() == max_results {
+                    truncated = true;
+                    return Visit::Stop;
+                }
+                matches.push(entry.path.to_string());
+            }
+            Visit::Continue
+        })?;
+    }
+
+    matches.sort();
+    Ok(Some(json!({
+        "path": root,
+        "pattern": pattern,
+        "count": matches.len(),
+        "matches": matches,
+        "truncated": truncated,
+    })))
+}
+
+/// Searches the text files below `root` (or `root` itself, if it is a
+/// file) for lines matching `pattern`.
+pub fn search(
+    root: &str,
+    pattern: &str,
+    options: &SearchOptions,
+    rules: &[PermissionRule],
+) -> FsResult {
+    log(&format!("Searching for {} in: {}", pattern, root));
+    let pattern_source = if options.regex {
+        pattern.to_string()
+    } else {
+        regex::escape(pattern)
+    };
+    let regex = RegexBuilder::new(&pattern_source)
+        .case_insensitive(!options.case_sensitive)
+        .build()
+        .map_err(|e| FsError::new(ErrorCode::InvalidRequest, format!("Invalid pattern: {}", e)))?;
+    let include = compile_globs(&options.include)?;
+    let exclude = compile_globs(&options.exclude)?;
+    let max_matches = options.max_matches.unwrap_or(DEFAULT_MAX_MATCHES);
+
+    let mut files = Vec::new();
+    if filesystem::list_files(root).is_ok() {
+        walk(root, rules, None, |entry| {
+            if exclude.iter().any(|glob| glob.is_match(entry.relative)) {
+                return Visit::Skip;
+            }
+            if !entry.is_dir
+                && (include.is_empty() || include.iter().any(|glob| glob.is_match(entry.relative)))
+            {
+                files.push(entry.path.to_string());
+            }
+            Visit::Continue
+        })?;
+    } else {
+        files.push(root.to_string());
+    }
+
+    let mut matches = Vec::new();
+    let mut truncated = false;
+    let mut files_searched = 0;
+    let mut binary_files_skipped = 0;
+    for path in files {
+        let Ok(content) = filesystem::read_file(&path) else {
+            continue;
+        };
+        let Some(text) = as_text(&content) else {
+            binary_files_skipped += 1;
+            continue;
+        };
+        files_searched += 1;
+        if !search_text(
+            &path,
+            text,
+            &regex,
+            options.context,
+            max_matches,
+            &mut matches,
+        ) {
+            truncated = true;
+            break;
+        }
+    }
+
+    Ok(Some(json!({
+        "path": root,
+        "pattern": pattern,
+        "count": matches.len(),
+        "matches": matches,
+        "truncated": truncated,
+        "files_searched": files_searched,
+        "binary_files_skipped": binary_files_skipped,
+    })))
+}
+
+/// Returns the file's text, or `None` if it looks binary.
+fn as_text(content: &[u8]) -> Option<&str> {
+    let sniff = &content[..content.len().min(BINARY_SNIFF_LEN)];
+    if sniff.contains(&0) {
+        return None;
+    }
+    std::str::from_utf8(content).ok()
+}
+
+/// Appends the matches in `text` to `matches`, returning false if
+/// `max_matches` was reached before the end of the file.
+fn search_text(
+    path: &str,
+    text: &str,
+    regex: &Regex,
+    context: usize,
+    max_matches: usize,
+    matches: &mut Vec<Match>,
+) -> bool {
+    let lines: Vec<&str> = text.lines().collect();
+    for (i, line) in lines.iter().enumerate() {
+        if !regex.is_match(line) {
+            continue;
+        }
+        if matches.len() == max_matches {
+            return false;
+        }
+        let before = i.saturating_sub(context);
+        let after = (i + 1).saturating_add(context).min(lines.len());
+        matches.push(Match {
+            path: path.to_string(),
+            line_number: i + 1,
+            line: line.to_string(),
+            before: lines[before..i].iter().map(|l| l.to_string()).collect(),
+            after: lines[i + 1..after].iter().map(|l| l.to_string()).collect(),
+        });
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{as_text, search_text};
+    use regex::Regex;
+
+    #[test]
+    fn binary_content_is_not_text() {
+        assert_eq!(as_text(b"plain text"), Some("plain text"));
+        assert_eq!(as_text(b"nul\0byte"), None);
+        assert_eq!(as_text(b"bad \xff utf-8"), None);
+    }
+
+    #[test]
+    fn matches_carry_context_lines() {
+        let regex = Regex::new("two|four").unwrap();
+        let mut matches = Vec::new();
+        assert!(search_text(
+            "f",
+            "one\ntwo\nthree\nfour\n",
+            &regex,
+            1,
+            10,
+            &mut matches
+        ));
+        assert_eq!(matches.len(), 2);
+        assert_eq!(matches[0].line_number, 2);
+        assert_eq!(matches[0].before, ["one"]);
+        assert_eq!(matches[0].after, ["three"]);
+        assert_eq!(matches[1].line, "four");
+        assert!(matches[1].after.is_empty());
+    }
+
+    #[test]
+    fn huge_context_is_clamped() {
+        let regex = Regex::new("b").unwrap();
+        let mut matches = Vec::new();
+        search_text("f", "a\nb\nc", &regex, usize::MAX, 10, &mut matches);
+        assert_eq!(matches[0].before, ["a"]);
+        assert_eq!(matches[0].after, ["c"]);
+    }
+
+    #[test]
+    fn stops_at_max_matches() {
+        let regex = Regex::new("x").unwrap();
+        let mut matches = Vec::new();
+        assert!(!search_text("f", "x\nx\nx", &regex, 0, 2, &mut matches));
+        assert_eq!(matches.len(), 2);
+    }
+}
@@ -0,0 +1,127 @@
+//! In-memory copies of files and directory trees.
+//!
+//! The host only exposes per-file reads and writes, so anything that has to
+//! move or put back a whole tree (batch rollback, `move`) loads it into a
+//! `Snapshot` first and writes it out again with `restore`.
+
+use crate::bindings::ntwk::theater::filesystem;
+use crate::ops::is_dir;
+use crate::path::join;
+use crate::response::FsError;
+
+/// What is at a path: nothing, a text file, or a directory of entries.
+pub enum Snapshot {
+    Missing,
+    File(String),
+    Dir(Vec<(String, Snapshot)>),
+}
+
+impl Snapshot {
+    /// Paths of everything below the snapshot root, relative to it.
+    pub fn descendants(&self) -> Vec<String> {
+        let mut paths = Vec::new();
+        if let Snapshot::Dir(children) = self {
+            for (name, child) in children {
+                paths.push(name.clone());
+                for path in child.descendants() {
+                    paths.push(format!("{}/{}", name, path));
+                }
+            }
+        }
+        paths
+    }
+}
+
+/// Reads `path` and everything below it into memory.
+pub fn snapshot(path: &str) -> Result<Snapshot, FsError> {
+    let exists =
+        filesystem::path_exists(path).map_err(|e| FsError::host("Failed to snapshot path", e))?;
+    if !exists {
+        return Ok(Snapshot::Missing);
+    }
+
+    if let Ok(entries) = filesystem::list_files(path) {
+        let mut children = Vec::new();
+        for name in entries {
+            let child = snapshot(&join(path, &name))?;
+            children.push((name, child));
+        }
+        return Ok(Snapshot::Dir(children));
+    }
+
+    let content =
+        filesystem::read_file(path).map_err(|e| FsError::host("Failed to snapshot file", e))?;
+    // write-file only takes text, so binary contents couldn't be written back
+    String::from_utf8(content)
+        .map(Snapshot::File)
+        .map_err(|_| FsError::not_text(path))
+}
+
+/// Makes `path` match `snapshot`, replacing whatever is there.
+pub fn restore(path: &str, snapshot: Snapshot) -> Result<(), String> {
+    let exists = filesystem::path_exists(path)?;
+    match snapshot {
+        Snapshot::Missing => {
+            if exists {
+                remove(path)?;
+            }
+        }
+        Snapshot::File(content) => {
+            if exists && is_dir(path) {
+                filesystem::delete_dir(path)?;
+            }
+            filesystem::write_file(path, &content)?;
+        }
+        Snapshot::Dir(children) => {
+            if exists && !is_dir(path) {
+                filesystem::delete_file(path)?;
+                filesystem::create_dir(path)?;
+            } else if !exists {
+                filesystem::create_dir(path)?;
+            }
+            // Entries created since the snapshot was taken
+            let current = filesystem::list_files(path)?;
+            for name in added(&current, &children) {
+                remove(&join(path, name))?;
+            }
+            for (name, child) in children {
+                restore(&join(path, &name), child)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The names in `current` that aren't among the snapshot's `children`.
+fn added<'a>(current: &'a [String], children: &[(String, Snapshot)]) -> Vec<&'a str> {
+    current
+        .iter()
+        .filter(|name| !children.iter().any(|(child, _)| child == *name))
+        .map(|name| name.as_str())
+        .collect()
+}
+
+/// Deletes a file or directory.
+pub fn remove(path: &str) -> Result<(), String> {
+    if is_dir(path) {
+        filesystem::delete_dir(path)
+    } else {
+        filesystem::delete_file(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{added, Snapshot};
+
+    #[test]
+    fn entries_missing_from_the_snapshot_are_added() {
+        let children = vec![
+            ("a".to_string(), Snapshot::File(String::new())),
+            ("dir".to_string(), Snapshot::Dir(Vec::new())),
+        ];
+        let current = ["a", "new", "dir", "other"].map(String::from);
+        assert_eq!(added(&current, &children), ["new", "other"]);
+        assert!(added(&[], &children).is_empty());
+    }
+}
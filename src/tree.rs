@@ -0,0 +1,74 @@
+//! Recursive directory listing.
+
+use crate::bindings::ntwk::theater::filesystem;
+use crate::logging::log;
+use crate::response::FsResult;
+use crate::walk::{compile_globs, walk, Visit};
+use crate::State;
+use serde::Serialize;
+use serde_json::json;
+use std::collections::BTreeMap;
+
+const MAX_CACHED_SIZES: usize = 1024;
+
+#[derive(Debug, Serialize)]
+struct Entry {
+    path: String,
+    kind: &'static str,
+    size: Option<u64>,
+}
+
+pub fn tree(
+    path: &str,
+    max_depth: Option<usize>,
+    include: &[String],
+    exclude: &[String],
+    state: &mut State,
+) -> FsResult {
+    log(&format!("Listing tree of: {}", path));
+    let include = compile_globs(include)?;
+    let exclude = compile_globs(exclude)?;
+    let size_cache = &mut state.size_cache;
+    let mut entries = Vec::new();
+    walk(path, &state.rules, max_depth, |entry| {
+        if exclude.iter().any(|glob| glob.is_match(entry.relative)) {
+            return Visit::Skip;
+        }
+        if include.is_empty() || include.iter().any(|glob| glob.is_match(entry.relative)) {
+            entries.push(Entry {
+                path: entry.path.to_string(),
+                kind: if entry.is_dir { "directory" } else { "file" },
+                size: if entry.is_dir {
+                    None
+                } else {
+                    size(entry.path, size_cache)
+                },
+            });
+        }
+        Visit::Continue
+    })?;
+
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(Some(json!({
+        "path": path,
+        "count": entries.len(),
+        "entries": entries,
+    })))
+}
+
+/// The host has no stat import, so sizes come from reading the file and are
+/// cached in the actor state until the file is next modified through this
+/// actor. Changes made by anything else aren't seen, so a cached size can be
+/// stale.
+fn size(path: &str, cache: &mut BTreeMap<String, u64>) -> Option<u64> {
+    if let Some(size) = cache.get(path) {
+        return Some(*size);
+    }
+    let size = filesystem::read_file(path).ok()?.len() as u64;
+    // The state is serialized on every request, so keep it small
+    if cache.len() >= MAX_CACHED_SIZES {
+        cache.pop_first();
+    }
+    cache.insert(path.to_string(), size);
+    Some(size)
+}
@@ -0,0 +1,98 @@
+//! Depth-first traversal of a directory tree through the host imports.
+
+use crate::bindings::ntwk::theater::filesystem;
+use crate::glob::Glob;
+use crate::path::join;
+use crate::permissions::{self, Permission, PermissionRule};
+use crate::response::{ErrorCode, FsError};
+
+pub struct Entry<'a> {
+    /// Path relative to the handler root.
+    pub path: &'a str,
+    /// Path relative to the directory being walked.
+    pub relative: &'a str,
+    pub is_dir: bool,
+}
+
+pub enum Visit {
+    Continue,
+    /// Don't descend into this directory.
+    Skip,
+    Stop,
+}
+
+/// Walks everything below `root`, calling `visit` for each entry. Entries
+/// the rules don't allow reading are left out. `max_depth` of 1 visits only
+/// the immediate children of `root`.
+pub fn walk(
+    root: &str,
+    rules: &[PermissionRule],
+    max_depth: Option<usize>,
+    mut visit: impl FnMut(&Entry) -> Visit,
+) -> Result<(), FsError> {
+    let names =
+        filesystem::list_files(root).map_err(|e| FsError::host("Failed to list files", e))?;
+    walk_dir(root, "", 1, names, rules, max_depth, &mut visit);
+    Ok(())
+}
+
+/// Returns false once the walk has been stopped.
+fn walk_dir(
+    dir: &str,
+    relative: &str,
+    depth: usize,
+    mut names: Vec<String>,
+    rules: &[PermissionRule],
+    max_depth: Option<usize>,
+    visit: &mut impl FnMut(&Entry) -> Visit,
+) -> bool {
+    names.sort();
+    for name in names {
+        let path = join(dir, &name);
+        if permissions::check(rules, Permission::Read, &path).is_err() {
+            continue;
+        }
+        let relative = if relative.is_empty() {
+            name
+        } else {
+            format!("{}/{}", relative, name)
+        };
+
+        let children = filesystem::list_files(&path).ok();
+        let entry = Entry {
+            path: &path,
+            relative: &relative,
+            is_dir: children.is_some(),
+        };
+        match visit(&entry) {
+            Visit::Stop => return false,
+            Visit::Skip => {}
+            Visit::Continue => {
+                if let Some(children) = children {
+                    if max_depth.is_none_or(|max| depth < max)
+                        && !walk_dir(
+                            &path,
+                            &relative,
+                            depth + 1,
+                            children,
+                            rules,
+                            max_depth,
+                            visit,
+                        )
+                    {
+                        return false;
+                    }
+                }
+            }
+        }
+    }
+    true
+}
+
+/// Compiles include/exclude filters, rejecting malformed patterns.
+pub fn compile_globs(patterns: &[String]) -> Result<Vec<Glob>, FsError> {
+    patterns
+        .iter()
+        .map(|pattern| Glob::new(pattern).map_err(|e| FsError::new(ErrorCode::InvalidRequest, e)))
+        .collect()
+}